@@ -0,0 +1,806 @@
+use crate::{CommunicateOutput, ProcessOutput, TestAgent, TestContext};
+use async_trait::async_trait;
+use regex::bytes::Regex;
+
+pub struct ExitCodeAgent {
+    accepted: Vec<i32>,
+}
+
+impl ExitCodeAgent {
+    pub fn new(accepted: &[i32]) -> Self {
+        Self { accepted: accepted.to_vec() }
+    }
+}
+
+#[async_trait]
+impl TestAgent for ExitCodeAgent {
+    async fn validate(
+        &self,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        let code = match output.status {
+            Ok(status) => status.code(),
+            Err(_) => None,
+        };
+
+        match code {
+            Some(code) if self.accepted.contains(&code) => true,
+            Some(code) => {
+                println!(
+                    "[-] Exit code {} is not one of {:?}",
+                    code, self.accepted
+                );
+                false
+            }
+            None => {
+                println!("[-] Test did not exit with a status code");
+                false
+            }
+        }
+    }
+}
+
+pub struct RegexAgent {
+    regex: Regex,
+}
+
+impl RegexAgent {
+    // an anchored pattern must match the whole of stdout
+    pub fn new(pattern: &str, anchored: bool) -> Result<Self, regex::Error> {
+        let regex = match anchored {
+            true => Regex::new(&format!("^(?:{})$", pattern))?,
+            false => Regex::new(pattern)?,
+        };
+        Ok(Self { regex })
+    }
+}
+
+#[async_trait]
+impl TestAgent for RegexAgent {
+    async fn validate(
+        &self,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        // `$` does not match before a final newline, which nearly every
+        // program prints, so it is not part of the matched text
+        let stdout =
+            output.stdout.strip_suffix(b"\n").unwrap_or(&output.stdout);
+        let stdout = stdout.strip_suffix(b"\r").unwrap_or(stdout);
+
+        if self.regex.is_match(stdout) {
+            return true;
+        }
+
+        println!("[-] Output does not match: {}", self.regex.as_str());
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Tolerance {
+    pub fn absolute(absolute: f64) -> Self {
+        Self { absolute, relative: 0.0 }
+    }
+
+    pub fn relative(relative: f64) -> Self {
+        Self { absolute: 0.0, relative }
+    }
+
+    // a value passes when it is within either bound
+    pub fn accepts(&self, actual: f64, expected: f64) -> bool {
+        let diff = (actual - expected).abs();
+        diff <= self.absolute || diff <= self.relative * expected.abs()
+    }
+}
+
+pub fn compare_numbers(
+    output: &str,
+    expected: &[f64],
+    tolerance: &Tolerance,
+) -> Result<(), String> {
+    let mut actual = Vec::new();
+    for (i, token) in output.split_whitespace().enumerate() {
+        match token.parse::<f64>() {
+            Ok(value) => actual.push(value),
+            Err(_) => {
+                return Err(format!("value {} is not a number: {}", i, token))
+            }
+        }
+    }
+
+    if actual.len() != expected.len() {
+        return Err(format!(
+            "expected {} numbers, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        if !tolerance.accepts(*a, *e) {
+            return Err(format!(
+                "value {} diverged: got {}, expected {} (off by {})",
+                i,
+                a,
+                e,
+                (a - e).abs()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// compares the lines as multisets, reporting the lines missing from the
+// output and those not expected
+pub fn compare_lines_unordered(
+    output: &[u8],
+    expected: &[u8],
+) -> Result<(), String> {
+    let lines = |bytes: &[u8]| -> Vec<String> {
+        let text = String::from_utf8_lossy(bytes);
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        lines.sort();
+        lines
+    };
+    let (mut actual, mut expected) = (lines(output), lines(expected));
+    if actual == expected {
+        return Ok(());
+    }
+
+    // drop the lines present in both, what remains is the difference
+    let mut missing = Vec::new();
+    for line in expected.drain(..) {
+        match actual.iter().position(|a| *a == line) {
+            Some(i) => {
+                actual.remove(i);
+            }
+            None => missing.push(line),
+        }
+    }
+
+    Err(format!(
+        "missing lines: {:?}, unexpected lines: {:?}",
+        missing, actual
+    ))
+}
+
+// outputs past this many lines are not diffed in full, the table is
+// quadratic in their length
+const DIFF_MAX_LINES: usize = 2000;
+
+// a line diff of two outputs, "-" for expected lines and "+" for actual
+// ones, with unchanged lines omitted
+pub fn line_diff(expected: &[u8], actual: &[u8]) -> String {
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    diff_lines(
+        &expected.lines().collect::<Vec<_>>(),
+        &actual.lines().collect::<Vec<_>>(),
+    )
+}
+
+// tabs, carriage returns and line feeds as visible marks
+pub fn visible_control_chars(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' => '\u{2192}',
+            '\r' => '\u{240d}',
+            '\n' => '\u{240a}',
+            c => c,
+        })
+        .collect()
+}
+
+// like line_diff, with visible_control_chars applied to every line so
+// otherwise identical looking lines show what sets them apart, a missing
+// final newline and CRLF endings included
+pub fn line_diff_visible(expected: &[u8], actual: &[u8]) -> String {
+    let visible_lines = |bytes: &[u8]| -> Vec<String> {
+        String::from_utf8_lossy(bytes)
+            .split_inclusive('\n')
+            .map(visible_control_chars)
+            .collect()
+    };
+    let (old, new) = (visible_lines(expected), visible_lines(actual));
+    diff_lines(
+        &old.iter().map(String::as_str).collect::<Vec<_>>(),
+        &new.iter().map(String::as_str).collect::<Vec<_>>(),
+    )
+}
+
+fn diff_lines(old: &[&str], new: &[&str]) -> String {
+    if old.len() > DIFF_MAX_LINES || new.len() > DIFF_MAX_LINES {
+        let line = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+        return format!(
+            "first difference at line {}\n-{}\n+{}\n",
+            line + 1,
+            old.get(line).unwrap_or(&""),
+            new.get(line).unwrap_or(&"")
+        );
+    }
+
+    // lcs[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1])
+        {
+            diff.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+pub struct NumericAgent {
+    expected: Vec<f64>,
+    tolerance: Tolerance,
+}
+
+impl NumericAgent {
+    pub fn new(expected: &[f64], tolerance: Tolerance) -> Self {
+        Self {
+            expected: expected.to_vec(),
+            tolerance,
+        }
+    }
+}
+
+#[async_trait]
+impl TestAgent for NumericAgent {
+    async fn validate(
+        &self,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match compare_numbers(&stdout, &self.expected, &self.tolerance) {
+            Ok(()) => true,
+            Err(reason) => {
+                println!("[-] Numeric output mismatch: {}", reason);
+                false
+            }
+        }
+    }
+}
+
+// the JSON path (e.g. "$.items[2].name") of the first difference between the
+// values, with object keys in any order and numbers compared by value
+fn json_difference(
+    path: String,
+    actual: &serde_json::Value,
+    expected: &serde_json::Value,
+) -> Option<String> {
+    use serde_json::Value;
+    match (actual, expected) {
+        (Value::Object(a), Value::Object(e)) => {
+            if let Some(key) = e.keys().find(|key| !a.contains_key(*key)) {
+                return Some(format!("{}.{} is missing", path, key));
+            }
+            if let Some(key) = a.keys().find(|key| !e.contains_key(*key)) {
+                return Some(format!("{}.{} is not expected", path, key));
+            }
+            e.iter().find_map(|(key, e)| {
+                json_difference(format!("{}.{}", path, key), &a[key], e)
+            })
+        }
+        (Value::Array(a), Value::Array(e)) => {
+            if a.len() != e.len() {
+                return Some(format!(
+                    "{} has {} elements, expected {}",
+                    path,
+                    a.len(),
+                    e.len()
+                ));
+            }
+            a.iter().zip(e).enumerate().find_map(|(i, (a, e))| {
+                json_difference(format!("{}[{}]", path, i), a, e)
+            })
+        }
+        // 1 and 1.0 are the same number
+        (Value::Number(a), Value::Number(e)) if a.as_f64() == e.as_f64() => {
+            None
+        }
+        (a, e) if a == e => None,
+        (a, e) => Some(format!("{} is {}, expected {}", path, a, e)),
+    }
+}
+
+// parses both as JSON and compares the values, so key order and formatting
+// do not matter
+pub fn compare_json(output: &[u8], expected: &[u8]) -> Result<(), String> {
+    let expected: serde_json::Value = serde_json::from_slice(expected)
+        .map_err(|e| format!("expected output is not JSON: {}", e))?;
+    compare_json_value(output, &expected)
+}
+
+fn compare_json_value(
+    output: &[u8],
+    expected: &serde_json::Value,
+) -> Result<(), String> {
+    let actual: serde_json::Value = serde_json::from_slice(output)
+        .map_err(|e| format!("output is not JSON: {}", e))?;
+
+    match json_difference(String::from("$"), &actual, expected) {
+        Some(difference) => Err(difference),
+        None => Ok(()),
+    }
+}
+
+pub struct JsonAgent {
+    expected: serde_json::Value,
+}
+
+impl JsonAgent {
+    pub fn new(expected: serde_json::Value) -> Self {
+        Self { expected }
+    }
+
+    pub fn parse(expected: &str) -> serde_json::Result<Self> {
+        Ok(Self::new(serde_json::from_str(expected)?))
+    }
+}
+
+#[async_trait]
+impl TestAgent for JsonAgent {
+    async fn validate(
+        &self,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        match compare_json_value(&output.stdout, &self.expected) {
+            Ok(()) => true,
+            Err(reason) => {
+                println!("[-] JSON output mismatch: {}", reason);
+                false
+            }
+        }
+    }
+}
+
+// fails when the assignment compiled with warnings, e.g. under -Wall
+pub struct NoWarningsAgent;
+
+#[async_trait]
+impl TestAgent for NoWarningsAgent {
+    async fn validate_with_context(
+        &self,
+        context: &TestContext,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        _output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        match &context.compile_report {
+            Some(report) if report.has_warnings() => {
+                println!("[-] Compilation produced warnings");
+                false
+            }
+            Some(_) => true,
+            None => {
+                println!("[-] No compilation was run");
+                false
+            }
+        }
+    }
+}
+
+// passes when every sub-agent passes, all of them run so each failure
+// reason gets printed, communicate is delegated to the first agent
+pub struct CompositeAgent {
+    agents: Vec<Box<dyn TestAgent>>,
+}
+
+impl CompositeAgent {
+    pub fn new(agents: Vec<Box<dyn TestAgent>>) -> Self {
+        Self { agents }
+    }
+
+    pub fn with(mut self, agent: Box<dyn TestAgent>) -> Self {
+        self.agents.push(agent);
+        self
+    }
+
+    fn summarize(&self, failed: usize) -> bool {
+        if failed > 0 {
+            println!("[-] {} of {} checks failed", failed, self.agents.len());
+        }
+        failed == 0
+    }
+}
+
+#[async_trait]
+impl TestAgent for CompositeAgent {
+    async fn validate(
+        &self,
+        args: &Vec<String>,
+        communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        let mut failed = 0;
+        for agent in &self.agents {
+            let passed = agent
+                .validate(args, communicate_output.clone(), output.clone(), cwd)
+                .await;
+            if !passed {
+                failed += 1;
+            }
+        }
+        self.summarize(failed)
+    }
+
+    async fn validate_with_context(
+        &self,
+        context: &TestContext,
+        args: &Vec<String>,
+        communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        let mut failed = 0;
+        for agent in &self.agents {
+            let passed = agent
+                .validate_with_context(
+                    context,
+                    args,
+                    communicate_output.clone(),
+                    output.clone(),
+                    cwd,
+                )
+                .await;
+            if !passed {
+                failed += 1;
+            }
+        }
+        self.summarize(failed)
+    }
+
+    async fn communicate(
+        &self,
+        read_timeout: u64,
+        port: &str,
+        process_id: Option<i32>,
+    ) -> CommunicateOutput {
+        match self.agents.first() {
+            Some(agent) => {
+                agent.communicate(read_timeout, port, process_id).await
+            }
+            None => CommunicateOutput::default(),
+        }
+    }
+
+    fn failure_reason(&self) -> Option<String> {
+        let reasons: Vec<String> = self
+            .agents
+            .iter()
+            .filter_map(|agent| agent.failure_reason())
+            .collect();
+        (!reasons.is_empty()).then(|| reasons.join("; "))
+    }
+
+    async fn finalize(&self) {
+        for agent in &self.agents {
+            agent.finalize().await;
+        }
+    }
+
+    async fn validate_clients(
+        &self,
+        args: &Vec<String>,
+        communicate_outputs: Vec<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        let mut failed = 0;
+        for agent in &self.agents {
+            let passed = agent
+                .validate_clients(
+                    args,
+                    communicate_outputs.clone(),
+                    output.clone(),
+                    cwd,
+                )
+                .await;
+            if !passed {
+                failed += 1;
+            }
+        }
+        self.summarize(failed)
+    }
+
+    async fn validate_clients_with_context(
+        &self,
+        context: &TestContext,
+        args: &Vec<String>,
+        communicate_outputs: Vec<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        let mut failed = 0;
+        for agent in &self.agents {
+            let passed = agent
+                .validate_clients_with_context(
+                    context,
+                    args,
+                    communicate_outputs.clone(),
+                    output.clone(),
+                    cwd,
+                )
+                .await;
+            if !passed {
+                failed += 1;
+            }
+        }
+        self.summarize(failed)
+    }
+}
+
+// the failed checks of one run, see SoftAssertAgent
+#[derive(Debug, Default)]
+pub struct SoftAssertions {
+    messages: Vec<String>,
+}
+
+impl SoftAssertions {
+    // records `message` unless `condition` holds, returns the condition
+    pub fn check(
+        &mut self,
+        condition: bool,
+        message: impl Into<String>,
+    ) -> bool {
+        if !condition {
+            self.messages.push(message.into());
+        }
+        condition
+    }
+
+    pub fn fail(&mut self, message: impl Into<String>) {
+        self.messages.push(message.into());
+    }
+
+    pub fn passed(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn messages(&self) -> &[String] {
+        &self.messages
+    }
+}
+
+pub type SoftAssertFn =
+    Box<dyn Fn(&ProcessOutput, &mut SoftAssertions) + Send + Sync>;
+
+// runs every check of the closure and fails with all of their messages,
+// which become the outcome's reason
+pub struct SoftAssertAgent {
+    assertions: SoftAssertFn,
+    failures: std::sync::Mutex<Vec<String>>,
+}
+
+impl SoftAssertAgent {
+    pub fn new<F>(assertions: F) -> Self
+    where
+        F: Fn(&ProcessOutput, &mut SoftAssertions) + Send + Sync + 'static,
+    {
+        Self {
+            assertions: Box::new(assertions),
+            failures: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl TestAgent for SoftAssertAgent {
+    async fn validate(
+        &self,
+        _args: &Vec<String>,
+        _communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        _cwd: &std::path::PathBuf,
+    ) -> bool {
+        let mut assertions = SoftAssertions::default();
+        (self.assertions)(&output, &mut assertions);
+        for message in assertions.messages() {
+            println!("[-] {}", message);
+        }
+
+        let passed = assertions.passed();
+        *self.failures.lock().unwrap() = assertions.messages;
+        passed
+    }
+
+    fn failure_reason(&self) -> Option<String> {
+        let failures = self.failures.lock().unwrap();
+        (!failures.is_empty()).then(|| failures.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn printed(stdout: &str, code: i32) -> ProcessOutput {
+        let status = Ok(ExitStatus::from_raw(code << 8));
+        ProcessOutput::new(stdout.as_bytes().to_vec(), Vec::new(), status)
+    }
+
+    async fn validates(agent: &dyn TestAgent, output: ProcessOutput) -> bool {
+        agent.validate(&Vec::new(), None, output, &".".into()).await
+    }
+
+    #[tokio::test]
+    async fn composite_agent_requires_every_check() {
+        let agent =
+            CompositeAgent::new(vec![Box::new(ExitCodeAgent::new(&[0]))])
+                .with(Box::new(RegexAgent::new(r"\d+", true).unwrap()));
+        assert!(validates(&agent, printed("42\n", 0)).await);
+        assert!(!validates(&agent, printed("42\n", 1)).await);
+        assert!(!validates(&agent, printed("apples\n", 0)).await);
+        assert!(!validates(&agent, printed("apples\n", 1)).await);
+    }
+
+    #[tokio::test]
+    async fn soft_assertions_report_every_failed_check() {
+        let agent = SoftAssertAgent::new(|output, check| {
+            check.check(output.status.as_ref().unwrap().success(), "exit 0");
+            check.check(output.stdout_str() == "3\n", "prints 3");
+            check.check(output.stdout_str().ends_with('\n'), "ends a line");
+            check.check(output.stderr.is_empty(), "stderr is empty");
+        });
+        assert!(validates(&agent, printed("3\n", 0)).await);
+        assert_eq!(agent.failure_reason(), None);
+
+        assert!(!validates(&agent, printed("4", 1)).await);
+        assert_eq!(
+            agent.failure_reason().unwrap(),
+            "exit 0; prints 3; ends a line"
+        );
+    }
+
+    #[test]
+    fn compare_json_ignores_key_order_and_formatting() {
+        assert!(compare_json(br#"{"a":1,"b":2}"#, br#"{"b":2,"a":1}"#).is_ok());
+        assert!(compare_json(b"{ \"a\" :\n [1.0] }", br#"{"a":[1]}"#).is_ok());
+
+        let differs = |output: &str, expected: &str| {
+            compare_json(output.as_bytes(), expected.as_bytes()).unwrap_err()
+        };
+        let items = r#"{"items":[{"name":"a"},{"name":"c"}]}"#;
+        let expected = r#"{"items":[{"name":"a"},{"name":"b"}]}"#;
+        assert_eq!(
+            differs(items, expected),
+            r#"$.items[1].name is "c", expected "b""#
+        );
+        assert_eq!(differs(r#"{"a":1}"#, r#"{"a":1,"b":2}"#), "$.b is missing");
+        assert_eq!(differs("[1]", "[1,2]"), "$ has 1 elements, expected 2");
+        assert!(differs("not json", "{}").starts_with("output is not JSON"));
+    }
+
+    #[tokio::test]
+    async fn json_agent_compares_the_parsed_stdout() {
+        let agent = JsonAgent::parse(r#"{"a":1,"b":2}"#).unwrap();
+        assert!(validates(&agent, printed("{\"b\": 2, \"a\": 1}\n", 0)).await);
+        assert!(!validates(&agent, printed(r#"{"a":1}"#, 0)).await);
+    }
+
+    #[test]
+    fn line_diff_shows_only_the_changed_lines() {
+        let diff = line_diff(b"a\nb\nc\nd\n", b"a\nc\nx\nd\n");
+        assert_eq!(diff, "-b\n+x\n");
+        assert_eq!(line_diff(b"same\n", b"same\n"), "");
+    }
+
+    #[test]
+    fn visible_diffs_mark_tabs_and_line_endings() {
+        assert_eq!(
+            visible_control_chars("a\tb\r\n"),
+            "a\u{2192}b\u{240d}\u{240a}"
+        );
+        let diff = line_diff_visible(b"\tx = 1;\n", b"    x = 1;\n");
+        assert_eq!(diff, "-\u{2192}x = 1;\u{240a}\n+    x = 1;\u{240a}\n");
+        // identical text, told apart by the line ending
+        let crlf = line_diff_visible(b"ok\n", b"ok\r\n");
+        assert_eq!(crlf, "-ok\u{240a}\n+ok\u{240d}\u{240a}\n");
+        assert_eq!(line_diff(b"ok\n", b"ok"), "");
+        assert_eq!(line_diff_visible(b"ok\n", b"ok"), "-ok\u{240a}\n+ok\n");
+    }
+
+    #[test]
+    fn compare_lines_unordered_reports_the_difference() {
+        assert!(compare_lines_unordered(b"b\na\nb\n", b"b\nb\na").is_ok());
+        let error = compare_lines_unordered(b"a\nc\nc\n", b"a\nb\nc\n");
+        assert_eq!(
+            error.unwrap_err(),
+            r#"missing lines: ["b"], unexpected lines: ["c"]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn exit_code_agent_accepts_the_listed_codes() {
+        let agent = ExitCodeAgent::new(&[0]);
+        assert!(validates(&agent, printed("", 0)).await);
+        assert!(!validates(&agent, printed("", 2)).await);
+
+        let killed = ProcessOutput::new(
+            Vec::new(),
+            Vec::new(),
+            Ok(ExitStatus::from_raw(libc::SIGKILL)),
+        );
+        assert!(!validates(&agent, killed).await);
+    }
+
+    #[tokio::test]
+    async fn regex_agent_matches_stdout() {
+        let number = RegexAgent::new(r"\d+", true).unwrap();
+        assert!(validates(&number, printed("42\n", 0)).await);
+        assert!(!validates(&number, printed("42 apples\n", 0)).await);
+
+        let unanchored = RegexAgent::new(r"\d+", false).unwrap();
+        assert!(validates(&unanchored, printed("42 apples\n", 0)).await);
+        assert!(RegexAgent::new("(", true).is_err());
+    }
+
+    // printed digits of PI, not a stand-in for the constant
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn tolerance_accepts_either_bound() {
+        assert!(Tolerance::absolute(1e-3).accepts(3.14159, 3.1416));
+        assert!(!Tolerance::absolute(1e-6).accepts(3.14159, 3.1416));
+        assert!(Tolerance::relative(0.01).accepts(101.0, 100.0));
+        assert!(!Tolerance::relative(0.01).accepts(102.0, 100.0));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn compare_numbers_reports_the_divergence() {
+        let tolerance = Tolerance::absolute(1e-3);
+        assert!(compare_numbers("3.14159\n", &[3.1416], &tolerance).is_ok());
+        assert_eq!(
+            compare_numbers("1 2.5", &[1.0, 2.0], &tolerance),
+            Err(String::from(
+                "value 1 diverged: got 2.5, expected 2 (off by 0.5)"
+            ))
+        );
+        assert_eq!(
+            compare_numbers("1", &[1.0, 2.0], &tolerance),
+            Err(String::from("expected 2 numbers, got 1"))
+        );
+        assert!(compare_numbers("1 two", &[1.0, 2.0], &tolerance).is_err());
+    }
+}
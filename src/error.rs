@@ -0,0 +1,128 @@
+use crate::run::CompileError;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum Error {
+    // the program could not be started at all
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    Compile(CompileError),
+    // a setup step run before the compile failed, not the compiler
+    PreCompile(CompileError),
+    Io(std::io::Error),
+    Timeout(Duration),
+    // the submission failed validation
+    Validation(String),
+    // no active test has this name
+    UnknownTest(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Spawn { command, source } => {
+                write!(f, "failed to spawn {}: {}", command, source)
+            }
+            Error::Compile(e) => e.fmt(f),
+            Error::PreCompile(e) => {
+                write!(f, "pre-compile step failed: {}", e.command)
+            }
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Timeout(after) => write!(f, "timed out after {:?}", after),
+            Error::Validation(reason) => f.write_str(reason),
+            Error::UnknownTest(name) => write!(f, "unknown test: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. } => Some(source),
+            Error::Compile(e) | Error::PreCompile(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Timeout(_)
+            | Error::Validation(_)
+            | Error::UnknownTest(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(e: CompileError) -> Self {
+        Error::Compile(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    fn read(path: &str) -> Result<String, Error> {
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    fn compile(command: &str) -> Result<(), Error> {
+        Err(CompileError {
+            command: command.to_string(),
+            output: String::from("main.c:1: error"),
+        })?
+    }
+
+    #[test]
+    fn errors_convert_through_the_question_mark() {
+        let error = read("/no/such/file").unwrap_err();
+        assert!(
+            matches!(&error, Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound)
+        );
+        assert!(error.to_string().starts_with("io error: "));
+        assert!(error.source().is_some());
+
+        let error = compile("gcc main.c -o main").unwrap_err();
+        assert!(
+            matches!(&error, Error::Compile(e) if e.output == "main.c:1: error")
+        );
+        assert_eq!(error.to_string(), "compilation failed: gcc main.c -o main");
+    }
+
+    #[test]
+    fn errors_describe_themselves() {
+        let spawn = Error::Spawn {
+            command: String::from("./prog"),
+            source: std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        };
+        assert_eq!(
+            spawn.to_string(),
+            "failed to spawn ./prog: permission denied"
+        );
+        assert!(spawn.source().is_some());
+
+        let pre_compile = Error::PreCompile(CompileError {
+            command: String::from("make gen"),
+            output: String::new(),
+        });
+        assert_eq!(
+            pre_compile.to_string(),
+            "pre-compile step failed: make gen"
+        );
+        assert!(pre_compile.source().is_some());
+
+        let timeout = Error::Timeout(Duration::from_secs(3));
+        assert_eq!(timeout.to_string(), "timed out after 3s");
+        let validation = Error::Validation(String::from("no test named x"));
+        assert_eq!(validation.to_string(), "no test named x");
+        assert!(validation.source().is_none());
+        let unknown = Error::UnknownTest(String::from("x"));
+        assert_eq!(unknown.to_string(), "unknown test: x");
+        assert!(unknown.source().is_none());
+    }
+}
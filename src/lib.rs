@@ -1,6 +1,14 @@
+mod agents;
+mod error;
+mod net;
+pub mod report;
 mod run;
 mod test_manager;
 mod utils;
+pub use agents::*;
+pub use error::Error;
+pub use net::*;
+pub use report::{collect_environment, EnvironmentInfo};
 pub use run::*;
 pub use test_manager::*;
 pub use utils::*;
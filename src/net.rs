@@ -0,0 +1,373 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// the connection as the kernel sees it from our end, see
+// Communicator::connection_state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    // established, the server has sent nothing since we last sent
+    Idle,
+    // established, the server sent data after we last sent
+    Responded,
+    // the server closed or reset its end
+    Closed,
+}
+
+// tcp_info's tcpi_state for an open connection
+const TCP_ESTABLISHED: u8 = 1;
+
+pub struct Communicator {
+    stream: TcpStream,
+    // read past the end of the last JSON response
+    pending: Vec<u8>,
+}
+
+impl Communicator {
+    pub async fn connect(host: &str, port: &str) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(format!("{}:{}", host, port))
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::ConnectionRefused => std::io::Error::new(
+                    e.kind(),
+                    format!(
+                        "connection refused, nothing listens on {}:{}",
+                        host, port
+                    ),
+                ),
+                _ => e,
+            })?;
+        Ok(Self { stream, pending: Vec::new() })
+    }
+
+    // from TCP_INFO, so it holds for exchanges done through stream() too
+    pub fn connection_state(&self) -> std::io::Result<ConnectionState> {
+        let mut info = unsafe { std::mem::zeroed::<libc::tcp_info>() };
+        let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+        let queried = unsafe {
+            libc::getsockopt(
+                self.stream.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                &mut info as *mut libc::tcp_info as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if queried != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if info.tcpi_state != TCP_ESTABLISHED {
+            return Ok(ConnectionState::Closed);
+        }
+        // both are milliseconds ago, a longer time since receiving means
+        // nothing arrived after the last send
+        let silent = info.tcpi_bytes_received == 0
+            || info.tcpi_last_data_recv > info.tcpi_last_data_sent;
+        match silent {
+            true => Ok(ConnectionState::Idle),
+            false => Ok(ConnectionState::Responded),
+        }
+    }
+
+    // a TimedOut error for an exchange that got no response, telling a
+    // server that accepted and then went silent from one that hung up
+    pub fn timeout_error(&self, context: &str) -> std::io::Error {
+        let diagnosis = match self.connection_state() {
+            Ok(ConnectionState::Idle) => {
+                format!("server accepted but did not respond: {}", context)
+            }
+            Ok(ConnectionState::Closed) => format!(
+                "server closed the connection without responding: {}",
+                context
+            ),
+            _ => context.to_string(),
+        };
+        std::io::Error::new(std::io::ErrorKind::TimedOut, diagnosis)
+    }
+
+    pub fn stream(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    // sends `payload` to an echoing server and reads it back, returning
+    // the round-trip rate in bytes per second
+    pub async fn measure_throughput(
+        &mut self,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> std::io::Result<f64> {
+        let started = Instant::now();
+        let (mut reader, mut writer) = self.stream.split();
+
+        let round_trip = async {
+            let send = writer.write_all(payload);
+            let mut echoed = vec![0u8; payload.len()];
+            let receive = reader.read_exact(&mut echoed);
+            // write and read together, a large payload would otherwise
+            // fill both socket buffers and stall
+            tokio::try_join!(send, receive)?;
+
+            if echoed != payload {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "echoed payload differs from the one sent",
+                ));
+            }
+            Ok(())
+        };
+
+        let round_trip = tokio::time::timeout(timeout, round_trip).await;
+        round_trip.map_err(|_| {
+            self.timeout_error("throughput measurement timed out")
+        })??;
+
+        let elapsed = started.elapsed().as_secs_f64().max(f64::EPSILON);
+        Ok(payload.len() as f64 / elapsed)
+    }
+
+    // JSON lines: sends `request` serialized on one line and parses the
+    // next line as the response, malformed JSON is an InvalidData error
+    pub async fn request_json<T: Serialize>(
+        &mut self,
+        request: &T,
+        timeout: Duration,
+    ) -> std::io::Result<Value> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+
+        let exchange = async {
+            self.stream.write_all(&line).await?;
+            let mut chunk = [0u8; 4096];
+            loop {
+                if let Some(end) = self.pending.iter().position(|&b| b == b'\n')
+                {
+                    let response: Vec<u8> =
+                        self.pending.drain(..=end).collect();
+                    return Ok(response);
+                }
+                match self.stream.read(&mut chunk).await? {
+                    0 => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            "connection closed before a response",
+                        ))
+                    }
+                    n => self.pending.extend_from_slice(&chunk[..n]),
+                }
+            }
+        };
+
+        let response = tokio::time::timeout(timeout, exchange).await;
+        let response = response.map_err(|_| {
+            self.timeout_error("no response to the JSON request")
+        })??;
+
+        serde_json::from_slice(&response).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "malformed JSON response {:?}: {}",
+                    String::from_utf8_lossy(&response).trim_end(),
+                    e
+                ),
+            )
+        })
+    }
+}
+
+const CONNECT_MIN_BACKOFF: Duration = Duration::from_millis(10);
+const CONNECT_MAX_BACKOFF: Duration = Duration::from_millis(500);
+
+// retries connecting, doubling the delay between attempts, until the server
+// accepts or `timeout` elapses, reporting the last connect error
+pub async fn wait_for_port(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut backoff = CONNECT_MIN_BACKOFF;
+
+    loop {
+        let attempt = TcpStream::connect((host, port));
+        let error = match tokio::time::timeout_at(deadline, attempt).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => e,
+            Err(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("{}:{} did not accept connections", host, port),
+                ))
+            }
+        };
+
+        let now = tokio::time::Instant::now();
+        if now + backoff >= deadline {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!(
+                    "{}:{} did not accept connections: {}",
+                    host, port, error
+                ),
+            ));
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(CONNECT_MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // echoes everything on each accepted connection until it is closed
+    async fn echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = client.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn throughput_of_an_echo_server() {
+        let port = echo_server().await.to_string();
+        let mut communicator =
+            Communicator::connect("127.0.0.1", &port).await.unwrap();
+        let payload = vec![b'x'; 1 << 20];
+        let rate = communicator
+            .measure_throughput(&payload, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(rate > 0.0 && rate.is_finite());
+    }
+
+    // a port nothing listens on, for a moment at least
+    fn free_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn wait_for_port_outlasts_a_late_server() {
+        let port = free_port();
+        let server = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let listener =
+                TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            listener.accept().await.unwrap()
+        });
+        wait_for_port("127.0.0.1", port, Duration::from_secs(5))
+            .await
+            .unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_for_port_times_out_without_a_server() {
+        let port = free_port();
+        let error =
+            wait_for_port("127.0.0.1", port, Duration::from_millis(200))
+                .await
+                .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn request_json_round_trips_through_an_echo_server() {
+        let port = echo_server().await.to_string();
+        let mut communicator =
+            Communicator::connect("127.0.0.1", &port).await.unwrap();
+        let timeout = Duration::from_secs(5);
+        for request in [
+            serde_json::json!({"op": "add", "args": [1, 2]}),
+            serde_json::json!({"op": "quit"}),
+        ] {
+            let response = communicator.request_json(&request, timeout).await;
+            assert_eq!(response.unwrap(), request);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_malformed_json_response_is_invalid_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            client.write_all(b"{oops\n").await.unwrap();
+            // held open until the test ends
+            std::future::pending::<()>().await;
+        });
+
+        let mut communicator =
+            Communicator::connect("127.0.0.1", &port).await.unwrap();
+        let error = communicator
+            .request_json(&serde_json::json!({}), Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    // accepts one connection, then holds it open without a word or hangs up
+    async fn accepting_server(hang_up: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (client, _) = listener.accept().await.unwrap();
+            if hang_up {
+                drop(client);
+            } else {
+                let _held = client;
+                std::future::pending::<()>().await;
+            }
+        });
+        port.to_string()
+    }
+
+    #[tokio::test]
+    async fn a_silent_server_is_diagnosed_as_idle() {
+        let port = accepting_server(false).await;
+        let mut communicator =
+            Communicator::connect("127.0.0.1", &port).await.unwrap();
+        let timeout = Duration::from_millis(300);
+        let error = communicator
+            .request_json(&serde_json::json!({"op": "ping"}), timeout)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert!(error
+            .to_string()
+            .starts_with("server accepted but did not respond"));
+    }
+
+    #[tokio::test]
+    async fn a_hung_up_or_missing_server_is_diagnosed_as_such() {
+        let port = accepting_server(true).await;
+        let communicator =
+            Communicator::connect("127.0.0.1", &port).await.unwrap();
+        // until the server's FIN has arrived
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let error = communicator.timeout_error("no response");
+        assert_eq!(
+            error.to_string(),
+            "server closed the connection without responding: no response"
+        );
+
+        let port = free_port().to_string();
+        let refused = Communicator::connect("127.0.0.1", &port).await;
+        let message = refused.err().unwrap().to_string();
+        assert!(message.starts_with("connection refused"));
+    }
+}
@@ -0,0 +1,69 @@
+pub mod archive;
+pub mod csv;
+pub mod json;
+pub mod markdown;
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentInfo {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub compiler_version: Option<String>,
+    // seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if res != 0 {
+        return String::new();
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+// best-effort, a missing compiler is not an error
+fn compiler_version() -> Option<String> {
+    let output = std::process::Command::new("gcc")
+        .arg("--version")
+        .output()
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.to_string())
+}
+
+pub fn collect_environment() -> EnvironmentInfo {
+    EnvironmentInfo {
+        hostname: hostname(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        compiler_version: compiler_version(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_is_collected_into_the_json_header() {
+        let environment = collect_environment();
+        assert_eq!(environment.os, "linux");
+        assert!(environment.timestamp > 0);
+
+        let report = json::to_value(&[], &environment);
+        assert_eq!(report["environment"]["os"], "linux");
+        assert_eq!(report["environment"]["timestamp"], environment.timestamp);
+        assert_eq!(report["results"], serde_json::json!([]));
+    }
+}
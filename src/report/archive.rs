@@ -0,0 +1,49 @@
+use super::{collect_environment, EnvironmentInfo};
+use crate::TestOutcome;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedTest {
+    pub name: String,
+    // the resolved command line, with the valgrind or interpreter prefix
+    pub cmd_args: Vec<String>,
+    pub outcome: TestOutcome,
+    // as captured, before any prompt stripping or normalization
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+// a recorded run, see TestManager::record_run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunArchive {
+    pub environment: EnvironmentInfo,
+    pub tests: Vec<ArchivedTest>,
+}
+
+impl RunArchive {
+    pub fn new(tests: Vec<ArchivedTest>) -> Self {
+        Self {
+            environment: collect_environment(),
+            tests,
+        }
+    }
+
+    pub fn results(&self) -> Vec<(String, TestOutcome)> {
+        self.tests
+            .iter()
+            .map(|test| (test.name.clone(), test.outcome.clone()))
+            .collect()
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string(self)?;
+        std::fs::write(path, contents)
+    }
+}
+
+// loads a recorded run to inspect it without running anything
+pub fn replay(path: &Path) -> std::io::Result<RunArchive> {
+    let contents = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&contents)?)
+}
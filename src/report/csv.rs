@@ -0,0 +1,169 @@
+use crate::TestOutcome;
+use std::path::Path;
+
+// quoted when it holds a separator, a quote or a line break, with quotes
+// doubled
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// why the test did not pass, when the outcome records it
+fn reason(outcome: &TestOutcome) -> String {
+    match &outcome.reason {
+        Some(reason) => reason.clone(),
+        None if outcome.truncated_by_timeout => String::from("timed out"),
+        None => String::new(),
+    }
+}
+
+// the agent's metadata as `key=value` pairs sorted by key
+fn metadata(outcome: &TestOutcome) -> String {
+    let mut pairs: Vec<String> = outcome
+        .metadata
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    pairs.sort();
+    pairs.join("; ")
+}
+
+pub fn to_string(results: &[(String, TestOutcome)]) -> String {
+    let mut csv = String::from(
+        "name,status,exit_code,duration_ms,points,reason,metadata\n",
+    );
+    for (name, outcome) in results {
+        let exit_code = outcome
+            .exit_code
+            .map(|code| code.to_string())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{:?},{},{},{},{},{}\n",
+            field(name),
+            outcome.status,
+            exit_code,
+            outcome.duration.as_millis(),
+            outcome.points,
+            field(&reason(outcome)),
+            field(&metadata(outcome))
+        ));
+    }
+    csv
+}
+
+pub fn write(
+    results: &[(String, TestOutcome)],
+    path: &Path,
+) -> std::io::Result<()> {
+    std::fs::write(path, to_string(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PhaseTimings, TestStatus};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn outcome(status: TestStatus, reason: Option<&str>) -> TestOutcome {
+        TestOutcome {
+            suite: String::new(),
+            description: String::new(),
+            status,
+            exit_code: Some(1),
+            points: 0.0,
+            duration: Duration::from_millis(1500),
+            slow: false,
+            stdout_bytes: 0,
+            stdout_lines: 0,
+            stderr_bytes: 0,
+            stderr_lines: 0,
+            peak_rss_bytes: None,
+            allocs: None,
+            truncated_by_timeout: false,
+            socket_leak_detected: false,
+            stage_exit_codes: Vec::new(),
+            timings: PhaseTimings::default(),
+            metadata: HashMap::new(),
+            stdout_excerpt: None,
+            stderr_excerpt: None,
+            reason: reason.map(String::from),
+        }
+    }
+
+    // the rows of `csv`, unquoting fields the way a spreadsheet would
+    fn parse(csv: &str) -> Vec<Vec<String>> {
+        let (mut rows, mut row, mut field) =
+            (Vec::new(), Vec::new(), String::new());
+        let (mut quoted, mut chars) = (false, csv.chars().peekable());
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    field.push(chars.next().unwrap());
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => row.push(std::mem::take(&mut field)),
+                ('\n', false) => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (c, _) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn field_quotes_only_when_needed() {
+        assert_eq!(field("plain"), "plain");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn the_csv_parses_back() {
+        let reason = "expected \"3\", got \"4\",\nthen crashed";
+        let results = [
+            (
+                String::from("adds, twice"),
+                outcome(TestStatus::Failed, Some(reason)),
+            ),
+            (String::from("echo"), outcome(TestStatus::Passed, None)),
+        ];
+        let rows = parse(&to_string(&results));
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            [
+                "name",
+                "status",
+                "exit_code",
+                "duration_ms",
+                "points",
+                "reason",
+                "metadata"
+            ]
+        );
+        assert_eq!(
+            rows[1][..6],
+            ["adds, twice", "Failed", "1", "1500", "0", reason]
+        );
+        assert_eq!(rows[2][1], "Passed");
+    }
+
+    #[test]
+    fn metadata_is_written_sorted_by_key() {
+        let mut recorded = outcome(TestStatus::Passed, None);
+        recorded.metadata = HashMap::from([
+            (String::from("score"), String::from("8/10")),
+            (String::from("latency_ms"), String::from("12")),
+        ]);
+        assert_eq!(metadata(&recorded), "latency_ms=12; score=8/10");
+        let rows = parse(&to_string(&[(String::from("t"), recorded)]));
+        assert_eq!(rows[1][6], "latency_ms=12; score=8/10");
+    }
+}
@@ -0,0 +1,35 @@
+use super::{collect_environment, EnvironmentInfo};
+use crate::TestOutcome;
+use serde_json::{json, Value};
+use std::path::Path;
+
+pub fn to_value(
+    results: &[(String, TestOutcome)],
+    environment: &EnvironmentInfo,
+) -> Value {
+    let results: Vec<Value> = results
+        .iter()
+        .map(|(name, outcome)| {
+            let mut result = json!({ "name": name });
+            if let Value::Object(fields) = json!(outcome) {
+                result.as_object_mut().unwrap().extend(fields);
+            }
+            result
+        })
+        .collect();
+
+    json!({
+        "environment": environment,
+        "results": results,
+    })
+}
+
+// the environment of the machine writing the report is recorded as header
+pub fn write(
+    results: &[(String, TestOutcome)],
+    path: &Path,
+) -> std::io::Result<()> {
+    let report = to_value(results, &collect_environment());
+    let contents = serde_json::to_string_pretty(&report)?;
+    std::fs::write(path, contents)
+}
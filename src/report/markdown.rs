@@ -0,0 +1,56 @@
+use crate::TemplateInfo;
+
+// pipes would end a cell early and newlines the row
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+// one table row per template, e.g. for an assignment spec
+pub fn catalog(catalog: &[TemplateInfo]) -> String {
+    let mut table = String::from(
+        "| Test | Suite | Description | Points | Timeout | Tags |\n|---|---|---|---|---|---|\n",
+    );
+
+    for info in catalog {
+        let timeout = match info.timeout {
+            0 => String::from("none"),
+            secs => format!("{}s", secs),
+        };
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            cell(&info.name),
+            cell(&info.suite),
+            cell(&info.description),
+            info.points,
+            timeout,
+            cell(&info.tags.join(", "))
+        ));
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_escapes_cells_and_lists_points() {
+        let info = TemplateInfo {
+            name: String::from("pipe"),
+            suite: String::from("default"),
+            description: String::from("a | b\nc"),
+            timeout: 0,
+            soft_timeout: None,
+            points: 1.5,
+            tags: vec![String::from("valgrind"), String::from("xfail")],
+        };
+        let table = catalog(&[info]);
+        assert_eq!(
+            table.lines().nth(2),
+            Some(
+                "| pipe | default | a \\| b c | 1.5 | none | valgrind, xfail |"
+            )
+        );
+    }
+}
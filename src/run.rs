@@ -1,6 +1,19 @@
-use std::io::Write;
+use crate::{
+    is_executable, open_fd_count, peak_rss_bytes, resolve_executable,
+    socket_inodes, strip_ansi,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::process::ExitStatus;
-use tokio::io::AsyncReadExt;
+use std::str::Utf8Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
 
 #[cfg(unix)]
 use std::os::unix::process::ExitStatusExt;
@@ -20,6 +33,39 @@ pub struct ProcessOutput {
     pub stdout: Vec<u8>,
     pub stderr: Vec<u8>,
     pub status: Result<ExitStatus, std::io::Error>,
+    // killed on timeout, the output may stop mid-write
+    pub truncated_by_timeout: bool,
+    // the fail-on text whose appearance got the child killed
+    pub sentinel: Option<String>,
+    // the done-on text after which the child was killed, its status is that
+    // of the kill
+    pub done_sentinel: Option<String>,
+    // still running a grace period after its stdin was closed, and killed
+    pub blocked_on_input: bool,
+    // bytes the child wrote, more than kept with a tee or when discarded
+    pub stdout_len: usize,
+    pub stderr_len: usize,
+}
+
+// io::Error is not Clone, a copy keeps its kind and message
+fn clone_io_error(error: &std::io::Error) -> std::io::Error {
+    std::io::Error::new(error.kind(), error.to_string())
+}
+
+impl Clone for ProcessOutput {
+    fn clone(&self) -> Self {
+        Self {
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+            status: self.status.as_ref().copied().map_err(clone_io_error),
+            truncated_by_timeout: self.truncated_by_timeout,
+            sentinel: self.sentinel.clone(),
+            done_sentinel: self.done_sentinel.clone(),
+            blocked_on_input: self.blocked_on_input,
+            stdout_len: self.stdout_len,
+            stderr_len: self.stderr_len,
+        }
+    }
 }
 
 impl ProcessOutput {
@@ -28,143 +74,1324 @@ impl ProcessOutput {
         stderr: Vec<u8>,
         status: Result<ExitStatus, std::io::Error>,
     ) -> Self {
-        Self { stdout, stderr, status }
+        let (stdout_len, stderr_len) = (stdout.len(), stderr.len());
+        Self {
+            stdout,
+            stderr,
+            status,
+            truncated_by_timeout: false,
+            sentinel: None,
+            done_sentinel: None,
+            blocked_on_input: false,
+            stdout_len,
+            stderr_len,
+        }
+    }
+
+    // invalid UTF-8 sequences are replaced with U+FFFD
+    pub fn stdout_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.stdout)
+    }
+
+    pub fn stdout_strict(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.stdout)
+    }
+
+    pub fn stderr_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.stderr)
+    }
+
+    pub fn stderr_strict(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.stderr)
+    }
+
+    pub fn stdout_as(
+        &self,
+        encoding: Encoding,
+    ) -> Result<Cow<'_, str>, Utf8Error> {
+        encoding.decode(&self.stdout)
+    }
+
+    pub fn stderr_as(
+        &self,
+        encoding: Encoding,
+    ) -> Result<Cow<'_, str>, Utf8Error> {
+        encoding.decode(&self.stderr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    // replace invalid sequences with U+FFFD
+    #[default]
+    Lossy,
+    // reject output that is not valid UTF-8
+    Utf8,
+    // map every byte to the code point of the same value, never fails
+    Latin1,
+}
+
+impl Encoding {
+    pub fn decode(self, bytes: &[u8]) -> Result<Cow<'_, str>, Utf8Error> {
+        match self {
+            Encoding::Lossy => Ok(String::from_utf8_lossy(bytes)),
+            Encoding::Utf8 => std::str::from_utf8(bytes).map(Cow::Borrowed),
+            Encoding::Latin1 => {
+                Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect()))
+            }
+        }
+    }
+}
+
+pub async fn pipe_reader<R>(pipe: R) -> Vec<u8>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    read_pipe(pipe, None, Vec::new(), false, DEFAULT_READ_BUFFER)
+        .await
+        .0
+}
+
+// streams both pipes of a child into one log file as chunks arrive, keeping
+// only the last `tail` bytes of each in memory
+#[derive(Clone)]
+pub struct OutputTee {
+    log: Arc<tokio::sync::Mutex<tokio::fs::File>>,
+    tail: usize,
+    budget: Option<Arc<LogBudget>>,
+}
+
+impl OutputTee {
+    pub async fn create(
+        path: &std::path::Path,
+        tail: usize,
+    ) -> Result<Self, std::io::Error> {
+        let log = tokio::fs::File::create(path).await?;
+        Ok(Self {
+            log: Arc::new(tokio::sync::Mutex::new(log)),
+            tail,
+            budget: None,
+        })
+    }
+
+    // chunks the budget cannot cover are left out of the log
+    pub fn with_budget(mut self, budget: Arc<LogBudget>) -> Self {
+        self.budget = Some(budget);
+        self
     }
 }
 
-pub async fn pipe_reader<R>(mut pipe: R) -> Vec<u8>
+// the bytes the logs of a whole suite may still take, shared by its tests;
+// a write the rest cannot cover is skipped, warning the first time
+#[derive(Debug)]
+pub struct LogBudget {
+    remaining: AtomicU64,
+    exhausted: AtomicBool,
+}
+
+impl LogBudget {
+    pub fn new(bytes: u64) -> Self {
+        Self {
+            remaining: AtomicU64::new(bytes),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    // whether `len` more bytes may be written, taking them when they may
+    pub fn take(&self, len: usize) -> bool {
+        let taken = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                remaining.checked_sub(len as u64)
+            })
+            .is_ok();
+        if !taken && !self.exhausted.swap(true, Ordering::Relaxed) {
+            println!(
+                "[!] Log budget exhausted, further log writes are skipped"
+            );
+        }
+        taken
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining.load(Ordering::Relaxed)
+    }
+}
+
+pub async fn pipe_tee_reader<R>(pipe: R, tee: OutputTee) -> Vec<u8>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    read_pipe(pipe, Some(tee), Vec::new(), false, DEFAULT_READ_BUFFER)
+        .await
+        .0
+}
+
+// reports the first occurrence of `needle` in a pipe
+struct Sentinel {
+    needle: String,
+    seen: mpsc::Sender<String>,
+}
+
+// bytes asked of each read of a pipe
+pub const DEFAULT_READ_BUFFER: usize = 64 * 1024;
+
+// the bytes kept and the number read; a discarding reader drains the pipe
+// without keeping any
+async fn read_pipe<R>(
+    mut pipe: R,
+    tee: Option<OutputTee>,
+    mut sentinels: Vec<Sentinel>,
+    discard: bool,
+    read_buffer: usize,
+) -> (Vec<u8>, usize)
 where
     R: tokio::io::AsyncRead + Unpin,
 {
     let mut buffer = Vec::new();
-    let mut temp_buf = [0u8; 1024];
+    let mut total = 0;
+    let mut temp_buf = vec![0u8; read_buffer.max(1)];
+    // the end of the previous chunks, a sentinel may span two reads
+    let mut window = Vec::new();
     while let Ok(n) = pipe.read(&mut temp_buf).await {
         if n == 0 {
             break;
         }
-        buffer.extend_from_slice(&temp_buf[..n]);
+        let chunk = &temp_buf[..n];
+        total += n;
+
+        if !sentinels.is_empty() {
+            window.extend_from_slice(chunk);
+            sentinels.retain(|watch| {
+                let needle = watch.needle.as_bytes();
+                let found = window.windows(needle.len()).any(|w| w == needle);
+                if found {
+                    let _ = watch.seen.try_send(watch.needle.clone());
+                }
+                !found
+            });
+            // enough for the longest needle left to span into the next read
+            let keep = sentinels.iter().map(|w| w.needle.len()).max();
+            let keep = keep.unwrap_or(0).saturating_sub(1);
+            if window.len() > keep {
+                window.drain(..window.len() - keep);
+            }
+        }
+
+        if !discard {
+            buffer.extend_from_slice(chunk);
+        }
+        if let Some(tee) = &tee {
+            // a failing log must not stall the child on a full pipe
+            if tee.budget.as_ref().is_none_or(|budget| budget.take(n)) {
+                let _ = tee.log.lock().await.write_all(chunk).await;
+            }
+            // trim in batches so each byte is moved at most once per tail
+            if buffer.len() > tee.tail * 2 {
+                buffer.drain(..buffer.len() - tee.tail);
+            }
+        }
+    }
+
+    if let Some(tee) = &tee {
+        let _ = tee.log.lock().await.flush().await;
+        if buffer.len() > tee.tail {
+            buffer.drain(..buffer.len() - tee.tail);
+        }
     }
-    buffer
+    (buffer, total)
+}
+
+// yields each line as it arrives without its terminator, a trailing partial
+// line included; the channel closes at EOF
+pub fn pipe_line_reader<R>(pipe: R) -> mpsc::Receiver<String>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut reader = tokio::io::BufReader::new(pipe);
+        let mut line = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut line).await {
+            if n == 0 {
+                break;
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+            }
+
+            let text = String::from_utf8_lossy(&line).into_owned();
+            if tx.send(text).await.is_err() {
+                break;
+            }
+            line.clear();
+        }
+    });
+    rx
+}
+
+const TIMEOUT_SIGNAL_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Default)]
+pub struct SpawnOptions {
+    // without stdin or a script the child inherits ours, otherwise it is fed
+    // the bytes and sees EOF after them
+    pub stdin: Option<Vec<u8>>,
+    // stream the output to a log instead of holding it in full
+    pub tee: Option<OutputTee>,
+    // kill the child as soon as its stdout or stderr contains these
+    pub fail_on_stdout: Option<String>,
+    pub fail_on_stderr: Option<String>,
+    // stop the child as soon as its stdout contains this, as a clean exit
+    pub done_on_stdout: Option<String>,
+    // niceness of the child, clamped to -20..=19
+    pub nice: Option<i32>,
+    // stdout is a pseudo-terminal so isatty(1) holds, stderr stays a pipe
+    pub pty: bool,
+    // set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+    // kill the child if it outlives its stdin by this long, see
+    // ProcessOutput::blocked_on_input; needs stdin
+    pub input_grace: Option<Duration>,
+    // drain the pipe without keeping its bytes, only their count
+    pub discard_stdout: bool,
+    pub discard_stderr: bool,
+    // bytes per read of stdout and stderr, DEFAULT_READ_BUFFER without it
+    pub read_buffer: Option<usize>,
+    // written in steps instead of `stdin` when set
+    pub stdin_script: Option<StdinScript>,
+    // the child leads its own process group, killed as a whole once the
+    // child has ended so forked descendants do not outlive it
+    pub process_group: bool,
+}
+
+// stdin for an interactive session: each step's bytes are written once its
+// delay after the previous step has passed, EOF follows the last step
+#[derive(Debug, Clone, Default)]
+pub struct StdinScript {
+    pub steps: Vec<(Duration, Vec<u8>)>,
+}
+
+impl StdinScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(mut self, delay: Duration, bytes: impl Into<Vec<u8>>) -> Self {
+        self.steps.push((delay, bytes.into()));
+        self
+    }
+
+    // `line` followed by a newline
+    pub fn line(self, delay: Duration, line: &str) -> Self {
+        self.send(delay, format!("{}\n", line))
+    }
+
+    // keeps stdin open `delay` longer before EOF
+    pub fn close_after(self, delay: Duration) -> Self {
+        self.send(delay, Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for StdinScript {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            steps: vec![(Duration::ZERO, bytes)],
+        }
+    }
+}
+
+// a pseudo-terminal pair in raw mode, so the output is not rewritten
+// (e.g. "\n" to "\r\n"); both ends are close-on-exec, the child only
+// gets the slave through its stdout
+fn open_pty() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let (mut master, mut slave) = (-1, -1);
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    if opened != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let (master, slave) =
+        unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+            libc::cfmakeraw(&mut termios);
+            libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+        }
+        libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(slave.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+
+    Ok((master, slave))
 }
 
 pub struct TestSpawner {
     child: tokio::process::Child,
-    out_task: Option<tokio::task::JoinHandle<Vec<u8>>>,
-    err_task: Option<tokio::task::JoinHandle<Vec<u8>>>,
+    out_task: Option<tokio::task::JoinHandle<(Vec<u8>, usize)>>,
+    err_task: Option<tokio::task::JoinHandle<(Vec<u8>, usize)>>,
+    sentinel: Option<mpsc::Receiver<String>>,
+    done: Option<mpsc::Receiver<String>>,
+    // when stdin was written and closed, and the grace period after it
+    input_closed: Option<(oneshot::Receiver<Instant>, Duration)>,
+    // the child's process group until it has been killed
+    process_group: Option<i32>,
+    // how long starting the child took, zero when it was started elsewhere
+    spawn_duration: Duration,
 }
 
 impl TestSpawner {
     pub async fn new(
-        cmd_args: &Vec<String>,
+        cmd_args: &[String],
         cwd: &std::path::PathBuf,
         startup_delay: u64,
-    ) -> Self {
-        // construct the path to the executable
-        let elf_path = cwd.join(&cmd_args[0]);
+    ) -> Result<Self, std::io::Error> {
+        Self::with_options(
+            cmd_args,
+            cwd,
+            startup_delay,
+            SpawnOptions::default(),
+        )
+        .await
+    }
 
+    pub async fn with_stdin(
+        cmd_args: &[String],
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        stdin: Option<&[u8]>,
+    ) -> Result<Self, std::io::Error> {
+        let options = SpawnOptions {
+            stdin: stdin.map(<[u8]>::to_vec),
+            ..Default::default()
+        };
+        Self::with_options(cmd_args, cwd, startup_delay, options).await
+    }
+
+    pub async fn with_tee(
+        cmd_args: &[String],
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        stdin: Option<&[u8]>,
+        tee: OutputTee,
+    ) -> Result<Self, std::io::Error> {
+        let options = SpawnOptions {
+            stdin: stdin.map(<[u8]>::to_vec),
+            tee: Some(tee),
+            ..Default::default()
+        };
+        Self::with_options(cmd_args, cwd, startup_delay, options).await
+    }
+
+    pub async fn with_options(
+        cmd_args: &[String],
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        options: SpawnOptions,
+    ) -> Result<Self, std::io::Error> {
         // check if the executable exists
-        if !elf_path.exists() && elf_path.file_name().unwrap() != "valgrind" {
-            panic!("[-] Cannot run exercise, {:?} is not found", elf_path);
+        if resolve_executable(&cmd_args[0], cwd).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{:?} is not found", cwd.join(&cmd_args[0])),
+            ));
         }
 
-        let mut child = tokio::process::Command::new(&cmd_args[0])
+        let mut command = tokio::process::Command::new(&cmd_args[0]);
+        command
             .args(&cmd_args[1..])
             .current_dir(cwd)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
-            .spawn()
-            .expect("[!] Failed to start child process");
+            // a wait abandoned midway must not leave the child running
+            .kill_on_drop(true);
+        let script = options
+            .stdin_script
+            .clone()
+            .or_else(|| options.stdin.clone().map(StdinScript::from));
+        if script.is_some() {
+            command.stdin(std::process::Stdio::piped());
+        }
+
+        let mut pty_master = None;
+        if options.pty {
+            let (master, slave) = open_pty()?;
+            command.stdout(std::process::Stdio::from(slave));
+            pty_master = Some(master);
+        }
+
+        command.envs(options.env.iter().cloned());
+        if options.process_group {
+            command.process_group(0);
+        }
+
+        if let Some(nice) = options.nice {
+            set_niceness(&mut command, nice);
+        }
+
+        // e.g. EACCES on a file without the executable bit
+        let spawn_started = Instant::now();
+        let mut child = command.spawn().map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("failed to start {:?}: {}", cmd_args[0], e),
+            )
+        })?;
+        // the command holds our copy of the pty slave, the master only sees
+        // EOF once every copy is closed
+        drop(command);
+        tracing::debug!(phase = "spawn", pid = child.id());
+
+        let mut input_closed = None;
+        if let Some(script) = script {
+            let mut pipe = child.stdin.take().expect("[!] Failed to get stdin");
+            let (closed, closed_rx) = oneshot::channel();
+            if let Some(grace) = options.input_grace {
+                input_closed = Some((closed_rx, grace));
+            }
+            tokio::spawn(async move {
+                for (delay, bytes) in script.steps {
+                    tokio::time::sleep(delay).await;
+                    // the process may exit without consuming all of its input
+                    if pipe.write_all(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                drop(pipe);
+                let _ = closed.send(Instant::now());
+            });
+        }
+
+        // read while the startup delay runs so sentinels are caught early
+        let process_group = options.process_group;
+        let mut spawner = Self::from_child(child, options, pty_master);
+        spawner.input_closed = input_closed;
+        spawner.spawn_duration = spawn_started.elapsed();
+        if process_group {
+            spawner.process_group = spawner.id();
+        }
 
         if startup_delay > 0 {
             tokio::time::sleep(tokio::time::Duration::from_secs(startup_delay))
                 .await;
         }
+        tracing::debug!(phase = "ready");
+
+        Ok(spawner)
+    }
 
-        let stdout = child.stdout.take().expect("[!] Failed to get stdout");
+    fn from_child(
+        mut child: tokio::process::Child,
+        options: SpawnOptions,
+        pty_master: Option<OwnedFd>,
+    ) -> Self {
         let stderr = child.stderr.take().expect("[!] Failed to get stderr");
 
+        let (seen, sentinel) = mpsc::channel(2);
+        let (done_seen, done) = mpsc::channel(1);
+        let watch = |needle: Option<String>, seen: &mpsc::Sender<String>| {
+            needle
+                .filter(|needle| !needle.is_empty())
+                .map(|needle| Sentinel { needle, seen: seen.clone() })
+        };
+        let out_sentinel = watch(options.fail_on_stdout, &seen);
+        let err_sentinel = watch(options.fail_on_stderr, &seen);
+        let watching = out_sentinel.is_some() || err_sentinel.is_some();
+        let done_sentinel = watch(options.done_on_stdout, &done_seen);
+        let watching_done = done_sentinel.is_some();
+        let out_sentinels: Vec<Sentinel> =
+            out_sentinel.into_iter().chain(done_sentinel).collect();
+
         // Spawn asynchronous tasks to handle stdout and stderr
-        let out_task = tokio::spawn(pipe_reader(stdout));
-        let err_task = tokio::spawn(pipe_reader(stderr));
+        let read_buffer = options.read_buffer.unwrap_or(DEFAULT_READ_BUFFER);
+        let tee = options.tee;
+        // reading the master fails with EIO once the child is gone, which
+        // ends the read like EOF
+        let out_task = match pty_master {
+            Some(master) => {
+                let stdout = tokio::fs::File::from_std(master.into());
+                tokio::spawn(read_pipe(
+                    stdout,
+                    tee.clone(),
+                    out_sentinels,
+                    options.discard_stdout,
+                    read_buffer,
+                ))
+            }
+            None => {
+                let stdout =
+                    child.stdout.take().expect("[!] Failed to get stdout");
+                tokio::spawn(read_pipe(
+                    stdout,
+                    tee.clone(),
+                    out_sentinels,
+                    options.discard_stdout,
+                    read_buffer,
+                ))
+            }
+        };
+        let err_task = tokio::spawn(read_pipe(
+            stderr,
+            tee,
+            err_sentinel.into_iter().collect(),
+            options.discard_stderr,
+            read_buffer,
+        ));
 
         Self {
             child,
             out_task: Some(out_task),
             err_task: Some(err_task),
+            sentinel: watching.then_some(sentinel),
+            done: watching_done.then_some(done),
+            input_closed: None,
+            process_group: None,
+            spawn_duration: Duration::ZERO,
         }
     }
 }
 
 impl TestSpawner {
     pub fn id(&self) -> Option<i32> {
-        match self.child.id() {
-            Some(pid) => Some(pid as i32),
-            None => None,
+        self.child.id().map(|pid| pid as i32)
+    }
+
+    pub fn spawn_duration(&self) -> Duration {
+        self.spawn_duration
+    }
+
+    // the child's status if it exits within the grace period after `signal`
+    async fn interrupt(&mut self, signal: Option<i32>) -> Option<ExitStatus> {
+        let (signal, pid) = (signal?, self.id()?);
+        // the whole group when there is one, a negative pid addresses it
+        let target = self.process_group.map_or(pid, |pgid| -pgid);
+        if unsafe { libc::kill(target, signal) } != 0 {
+            return None;
+        }
+
+        tokio::time::timeout(TIMEOUT_SIGNAL_GRACE, self.child.wait())
+            .await
+            .ok()?
+            .ok()
+    }
+
+    // whatever the child left behind in its group, e.g. forked children
+    fn kill_process_group(&mut self) {
+        if let Some(pgid) = self.process_group.take() {
+            unsafe { libc::killpg(pgid, libc::SIGKILL) };
         }
     }
 }
 
+impl Drop for TestSpawner {
+    fn drop(&mut self) {
+        self.kill_process_group();
+    }
+}
+
 impl TestSpawner {
+    // a finish_timeout of 0 waits for the child indefinitely; the timeout
+    // runs on tokio's clock so a runtime with paused time advances it
+    // virtually
     pub async fn wait(&mut self, finish_timeout: u64) -> ProcessOutput {
-        let secs = tokio::time::Duration::from_secs(finish_timeout);
-        let result = tokio::time::timeout(secs, async {
-            let status = self.child.wait().await;
-            status
-        })
-        .await;
+        self.wait_with_signal(finish_timeout, None).await
+    }
 
+    // on timeout `signal` is sent first; a child exiting within
+    // TIMEOUT_SIGNAL_GRACE keeps its own status, otherwise it is killed
+    pub async fn wait_with_signal(
+        &mut self,
+        finish_timeout: u64,
+        signal: Option<i32>,
+    ) -> ProcessOutput {
+        let child = &mut self.child;
+        let exited = async {
+            if finish_timeout == 0 {
+                Ok(child.wait().await)
+            } else {
+                let secs = tokio::time::Duration::from_secs(finish_timeout);
+                tokio::time::timeout(secs, child.wait()).await
+            }
+        };
+        let seen = async {
+            match self.sentinel.as_mut() {
+                Some(sentinel) => match sentinel.recv().await {
+                    Some(needle) => needle,
+                    // both pipes closed without a match
+                    None => std::future::pending().await,
+                },
+                None => std::future::pending().await,
+            }
+        };
+        let done = async {
+            match self.done.as_mut() {
+                Some(done) => match done.recv().await {
+                    Some(needle) => needle,
+                    None => std::future::pending().await,
+                },
+                None => std::future::pending().await,
+            }
+        };
+        let input_closed = self.input_closed.take();
+        let blocked = async {
+            match input_closed {
+                Some((closed, grace)) => match closed.await {
+                    Ok(at) => tokio::time::sleep_until(at + grace).await,
+                    Err(_) => std::future::pending().await,
+                },
+                None => std::future::pending().await,
+            }
+        };
+
+        // a sentinel seen in the output or a child outliving its input end
+        // the wait early as Err, the done sentinel as Ok(Err(needle))
+        let result = tokio::select! {
+            result = exited => Ok(result.map_err(|_timeout| None)),
+            needle = seen => Err(Some(needle)),
+            _ = blocked => Err(None),
+            needle = done => Ok(Err(Some(needle))),
+        };
+
+        let mut truncated_by_timeout = false;
+        let mut sentinel = None;
+        let mut done_sentinel = None;
+        let mut blocked_on_input = false;
         let result = match result {
-            Ok(status) => status,
-            Err(_timeout) => {
+            Ok(Ok(status)) => status,
+            Ok(Err(Some(needle))) => {
                 self.child.kill().await.unwrap();
-                self.child.wait().await.unwrap();
-                // timed out - return corresponding status code
-                Ok(ExitStatus::from_raw(Status::Timeout as i32))
+                done_sentinel = Some(needle);
+                self.child.wait().await
+            }
+            Err(needle) => {
+                self.child.kill().await.unwrap();
+                blocked_on_input = needle.is_none();
+                sentinel = needle;
+                self.child.wait().await
             }
+            Ok(Err(None)) => match self.interrupt(signal).await {
+                Some(status) => Ok(status),
+                None => {
+                    self.child.kill().await.unwrap();
+                    self.child.wait().await.unwrap();
+                    truncated_by_timeout = true;
+                    // timed out - return corresponding status code
+                    Ok(ExitStatus::from_raw(Status::Timeout as i32))
+                }
+            },
         };
+        // before the pipes are drained, descendants may hold them open
+        self.kill_process_group();
 
-        let stdout = self
+        let (stdout, stdout_len) = self
             .out_task
             .take()
             .unwrap()
             .await
             .expect("[-] Failed to read stdout");
 
-        let stderr = self
+        let (stderr, stderr_len) = self
             .err_task
             .take()
             .unwrap()
             .await
             .expect("[-] Failed to read stderr");
 
-        ProcessOutput::new(stdout, stderr, result)
+        ProcessOutput {
+            truncated_by_timeout,
+            sentinel,
+            done_sentinel,
+            blocked_on_input,
+            stdout_len,
+            stderr_len,
+            ..ProcessOutput::new(stdout, stderr, result)
+        }
+    }
+}
+
+/// Runs a command in `cwd` and collects its output, killing it after
+/// `timeout` seconds (0 waits indefinitely), without any of the
+/// `Test`/`TestManager` machinery. A command that cannot be started is
+/// reported through `status`.
+///
+/// ```
+/// use tests_lib::*;
+///
+/// let rt = tokio::runtime::Runtime::new().unwrap();
+/// let cmd = [String::from("echo"), String::from("hello")];
+/// let output = rt.block_on(run_with_timeout(&cmd, &".".into(), 5));
+///
+/// assert_eq!(output.stdout_str(), "hello\n");
+/// assert!(output.status.unwrap().success());
+/// assert!(!output.truncated_by_timeout);
+/// ```
+pub async fn run_with_timeout(
+    cmd_args: &[String],
+    cwd: &std::path::PathBuf,
+    timeout: u64,
+) -> ProcessOutput {
+    match TestSpawner::new(cmd_args, cwd, 0).await {
+        Ok(mut spawner) => spawner.wait(timeout).await,
+        Err(e) => ProcessOutput::new(Vec::new(), Vec::new(), Err(e)),
     }
 }
 
+/// Runs a helper program (e.g. a reference checker) with `stdin` piped to it,
+/// bounded by the same timeout handling as the tests themselves.
+///
+/// ```no_run
+/// use tests_lib::*;
+///
+/// struct CheckerAgent;
+///
+/// #[async_trait::async_trait]
+/// impl TestAgent for CheckerAgent {
+///     async fn validate(
+///         &self,
+///         _args: &Vec<String>,
+///         _communicate_output: Option<CommunicateOutput>,
+///         output: ProcessOutput,
+///         cwd: &std::path::PathBuf,
+///     ) -> bool {
+///         let checker = vec![String::from("./checker")];
+///         let verdict = run_checker(&checker, &output.stdout, cwd, 5).await;
+///         matches!(verdict.status, Ok(status) if status.success())
+///     }
+/// }
+/// ```
+pub async fn run_checker(
+    cmd_args: &[String],
+    stdin: &[u8],
+    cwd: &std::path::PathBuf,
+    timeout: u64,
+) -> ProcessOutput {
+    let Some((program, args)) = cmd_args.split_first() else {
+        let e = std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "empty checker command",
+        );
+        return ProcessOutput::new(Vec::new(), Vec::new(), Err(e));
+    };
+    // a missing checker fails this validation, not the whole run
+    let spawned = tokio::process::Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => return ProcessOutput::new(Vec::new(), Vec::new(), Err(e)),
+    };
+
+    // feed stdin concurrently so a checker that writes before reading
+    // cannot deadlock against us; dropping the pipe signals EOF
+    let mut pipe = child.stdin.take().expect("[!] Failed to get stdin");
+    let input = stdin.to_vec();
+    tokio::spawn(async move {
+        // the checker may exit without consuming all of its input
+        let _ = pipe.write_all(&input).await;
+    });
+
+    TestSpawner::from_child(child, SpawnOptions::default(), None)
+        .wait(timeout)
+        .await
+}
+
+pub struct PipelineOutput {
+    // the last stage's stdout, the stages' stderr in stage order, and the
+    // last stage's status as a shell would report it
+    pub output: ProcessOutput,
+    // one per stage up to the first that could not start, which ends the
+    // list with its error
+    pub statuses: Vec<Result<ExitStatus, std::io::Error>>,
+    pub duration: Duration,
+}
+
+// best-effort, raising the priority needs privileges; only the
+// async-signal-safe setpriority runs between fork and exec
+fn set_niceness(command: &mut tokio::process::Command, nice: i32) {
+    let nice = nice.clamp(-20, 19);
+    unsafe {
+        command.pre_exec(move || {
+            libc::setpriority(libc::PRIO_PROCESS as _, 0, nice);
+            Ok(())
+        });
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PipelineOptions {
+    // fed to the first stage, which reads nothing without it
+    pub stdin: Option<Vec<u8>>,
+    // the index of the stage under test, the only one `env` and `nice`
+    // apply to
+    pub program: usize,
+    // set on top of the inherited environment
+    pub env: Vec<(String, String)>,
+    // niceness of the program, clamped to -20..=19
+    pub nice: Option<i32>,
+}
+
+// runs `./gen | ./prog | ./check` style stages, each stdout wired to the
+// next stage's stdin; stages still running after `timeout` are killed
+pub async fn run_pipeline(
+    stages: &[Vec<String>],
+    cwd: &std::path::PathBuf,
+    timeout: u64,
+    options: &PipelineOptions,
+) -> PipelineOutput {
+    let started = Instant::now();
+    let mut children = Vec::new();
+    let mut stderr_readers = Vec::new();
+    let mut stdout: Option<tokio::process::ChildStdout> = None;
+    let mut spawn_error = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let Some((program, args)) = stage.split_first() else {
+            spawn_error = Some(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "empty pipeline stage",
+            ));
+            break;
+        };
+        let stdin = match (stdout.take(), &options.stdin) {
+            (Some(previous), _) => previous
+                .try_into()
+                .expect("[!] Failed to pipe stdout to the next stage"),
+            (None, Some(_)) => std::process::Stdio::piped(),
+            (None, None) => std::process::Stdio::null(),
+        };
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .current_dir(cwd)
+            .stdin(stdin)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if i == options.program {
+            command.envs(options.env.iter().cloned());
+            if let Some(nice) = options.nice {
+                set_niceness(&mut command, nice);
+            }
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                spawn_error = Some(e);
+                break;
+            }
+        };
+        if let (Some(mut pipe), Some(input)) =
+            (child.stdin.take(), &options.stdin)
+        {
+            let input = input.clone();
+            tokio::spawn(async move {
+                // the stage may exit without consuming all of its input
+                let _ = pipe.write_all(&input).await;
+            });
+        }
+        stdout = child.stdout.take();
+        let stderr = child.stderr.take().expect("[!] Failed to get stderr");
+        stderr_readers.push(tokio::spawn(pipe_reader(stderr)));
+        children.push(child);
+    }
+    let stdout_reader = stdout.map(|pipe| tokio::spawn(pipe_reader(pipe)));
+
+    let mut truncated_by_timeout = false;
+    if spawn_error.is_some() {
+        for child in children.iter_mut() {
+            let _ = child.start_kill();
+        }
+    }
+    let exited =
+        futures::future::join_all(children.iter_mut().map(|c| c.wait()));
+    if timeout == 0 {
+        exited.await;
+    } else if tokio::time::timeout(Duration::from_secs(timeout), exited)
+        .await
+        .is_err()
+    {
+        truncated_by_timeout = true;
+    }
+
+    let mut statuses = Vec::new();
+    for child in children.iter_mut() {
+        let status = match child.try_wait() {
+            Ok(Some(status)) => Ok(status),
+            Ok(None) => {
+                let _ = child.kill().await;
+                Ok(ExitStatus::from_raw(Status::Timeout as i32))
+            }
+            Err(e) => Err(e),
+        };
+        statuses.push(status);
+    }
+    let duration = started.elapsed();
+
+    let stdout = match stdout_reader {
+        Some(reader) => reader.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let mut stderr = Vec::new();
+    for reader in stderr_readers {
+        stderr.extend(reader.await.unwrap_or_default());
+    }
+
+    let status = match (&spawn_error, statuses.last()) {
+        (Some(e), _) => Err(clone_io_error(e)),
+        (None, Some(Ok(status))) => Ok(*status),
+        (None, Some(Err(e))) => Err(clone_io_error(e)),
+        (None, None) => Err(std::io::Error::other("pipeline has no stages")),
+    };
+    statuses.extend(spawn_error.map(Err));
+
+    let mut output = ProcessOutput::new(stdout, stderr, status);
+    output.truncated_by_timeout = truncated_by_timeout;
+    PipelineOutput { output, statuses, duration }
+}
+
+// each sample reads /proc, often enough for peaks lasting a few samples
+// without loading a busy grading host
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+
+// samples the process's open descriptors until it exits, keeping the highest
+// count seen in `peak`; descriptors opened and closed between samples are missed
+pub async fn track_open_fds(pid: i32, peak: Arc<AtomicUsize>) {
+    while let Some(count) = open_fd_count(pid) {
+        peak.fetch_max(count, Ordering::Relaxed);
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+// the socket inodes seen by track_sockets, in any sample and in the latest
+#[derive(Debug, Clone, Default)]
+pub struct SocketSamples {
+    pub seen: HashSet<u64>,
+    pub last: HashSet<u64>,
+}
+
+// samples the process's sockets until it exits; sockets opened and closed
+// between samples are missed
+pub async fn track_sockets(
+    pid: i32,
+    samples: Arc<std::sync::Mutex<SocketSamples>>,
+) {
+    while let Some(inodes) = socket_inodes(pid) {
+        {
+            let mut samples = samples.lock().unwrap();
+            samples.seen.extend(&inodes);
+            samples.last = inodes;
+        }
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+// samples the process's peak resident memory until it exits; the kernel keeps
+// the high-water mark so only growth after the last sample is missed
+pub async fn track_peak_rss(pid: i32, peak: Arc<AtomicU64>) {
+    while let Some(bytes) = peak_rss_bytes(pid) {
+        peak.fetch_max(bytes, Ordering::Relaxed);
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileReport {
+    pub command: String,
+    // "error", "warning", "success" or "timeout", see CompileResult
+    pub status: String,
+    // compiler output as written to the compilation log
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl CompileReport {
+    // a timed out compilation failed as well
+    pub fn failed(&self) -> bool {
+        self.status == "error" || self.timed_out()
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.status == "timeout"
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.status == "warning"
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileResult {
+    Success,
+    Warning,
+    Error,
+    // the compiler was killed once the timeout elapsed
+    Timeout,
+}
+
+impl CompileResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompileResult::Success => "success",
+            CompileResult::Warning => "warning",
+            CompileResult::Error => "error",
+            CompileResult::Timeout => "timeout",
+        }
+    }
+
+    // no usable binary, an error or a timeout
+    pub fn failed(self) -> bool {
+        matches!(self, CompileResult::Error | CompileResult::Timeout)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub command: String,
+    // compiler output as written to the compilation log
+    pub output: String,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "compilation failed: {}", self.command)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+// the binary named by `-o <path>` (or `-o<path>`), else the last argument
+fn compile_output_path<'a>(args: &[&'a str]) -> &'a str {
+    for (i, arg) in args.iter().enumerate() {
+        if *arg == "-o" {
+            if let Some(path) = args.get(i + 1) {
+                return path;
+            }
+        } else if let Some(path) = arg.strip_prefix("-o") {
+            return path;
+        }
+    }
+
+    args.last().unwrap()
+}
+
+#[derive(Debug, Clone)]
+pub struct CompilePatterns {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Default for CompilePatterns {
+    fn default() -> Self {
+        Self {
+            errors: vec![String::from("error:")],
+            warnings: vec![String::from("warning")],
+        }
+    }
+}
+
+impl CompilePatterns {
+    fn matches(patterns: &[String], output: &[u8]) -> bool {
+        patterns.iter().any(|pattern| {
+            let needle = pattern.as_bytes();
+            !needle.is_empty()
+                && output.windows(needle.len()).any(|window| window == needle)
+        })
+    }
+}
+
+// how long a compile may run unless a timeout is given
+pub const DEFAULT_COMPILE_TIMEOUT: Duration = Duration::from_secs(120);
+
+// the status as a string, "success", "warning", "error" or "timeout"; the
+// compiler is killed after DEFAULT_COMPILE_TIMEOUT
 pub fn compile(input: &str, cwd: &std::path::PathBuf) -> String {
+    compile_with_timeout(input, cwd, DEFAULT_COMPILE_TIMEOUT)
+        .as_str()
+        .to_string()
+}
+
+// the compiler is killed once `timeout` elapses so a build that never ends
+// cannot hang the grader
+pub fn compile_with_timeout(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    timeout: Duration,
+) -> CompileResult {
+    compile_with_patterns(input, cwd, &CompilePatterns::default(), timeout)
+}
+
+pub fn compile_with_patterns(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    patterns: &CompilePatterns,
+    timeout: Duration,
+) -> CompileResult {
+    let log_path = cwd.join("compilation_output.txt");
+    compile_logged(input, cwd, patterns, &log_path, timeout)
+}
+
+// like compile, for callers on the async runtime
+pub async fn compile_async(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    timeout: Duration,
+) -> CompileResult {
+    let patterns = CompilePatterns::default();
+    compile_async_with_patterns(input, cwd, &patterns, timeout).await
+}
+
+pub async fn compile_async_with_patterns(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    patterns: &CompilePatterns,
+    timeout: Duration,
+) -> CompileResult {
+    let log_path = cwd.join("compilation_output.txt");
+    compile_logged_async(input, cwd, patterns, &log_path, timeout).await
+}
+
+// appended to a failing compile command for a more detailed log
+pub const DIAGNOSTIC_FLAGS: &[&str] =
+    &["-fdiagnostics-show-caret", "-Wall", "-Wextra"];
+
+pub(crate) fn compile_logged(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    patterns: &CompilePatterns,
+    log_path: &std::path::Path,
+    timeout: Duration,
+) -> CompileResult {
+    use std::io::Read;
+    use std::os::unix::process::CommandExt;
+
+    let elf_path = prepare_compile(input, cwd);
+    let mut command = std::process::Command::new("sh");
+    command.arg("-c").arg(input).current_dir(cwd);
+
+    // in its own group so the compiler sh started dies along with it
+    let mut child = command
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .expect("[-] Failed to run compilation command");
+    let drain = |mut pipe: Box<dyn Read + Send>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = pipe.read_to_end(&mut buf);
+            buf
+        })
+    };
+    let stdout = drain(Box::new(child.stdout.take().unwrap()));
+    let stderr = drain(Box::new(child.stderr.take().unwrap()));
+
+    let deadline = std::time::Instant::now() + timeout;
+    let mut timed_out = None;
+    let status = loop {
+        let status = child.try_wait().expect("[-] Failed to wait for compiler");
+        if let Some(status) = status {
+            break status;
+        }
+        if std::time::Instant::now() >= deadline {
+            unsafe { libc::killpg(child.id() as i32, libc::SIGKILL) };
+            timed_out = Some(timeout);
+            break child.wait().expect("[-] Failed to wait for compiler");
+        }
+        std::thread::sleep(COMPILE_POLL_INTERVAL);
+    };
+
+    let output = std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    finish_compile(&output, timed_out, &elf_path, patterns, log_path)
+}
+
+pub(crate) async fn compile_logged_async(
+    input: &str,
+    cwd: &std::path::PathBuf,
+    patterns: &CompilePatterns,
+    log_path: &std::path::Path,
+    timeout: Duration,
+) -> CompileResult {
+    let elf_path = prepare_compile(input, cwd);
+    let mut command = tokio::process::Command::new("sh");
+    command.arg("-c").arg(input).current_dir(cwd);
+
+    let mut child = command
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .expect("[-] Failed to run compilation command");
+    let stdout = tokio::spawn(pipe_reader(child.stdout.take().unwrap()));
+    let stderr = tokio::spawn(pipe_reader(child.stderr.take().unwrap()));
+
+    let mut timed_out = None;
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            if let Some(pid) = child.id() {
+                unsafe { libc::killpg(pid as i32, libc::SIGKILL) };
+            }
+            timed_out = Some(timeout);
+            child.wait().await
+        }
+    };
+
+    let output = std::process::Output {
+        status: status.expect("[-] Failed to wait for compiler"),
+        stdout: stdout.await.unwrap_or_default(),
+        stderr: stderr.await.unwrap_or_default(),
+    };
+    finish_compile(&output, timed_out, &elf_path, patterns, log_path)
+}
+
+// the binary the command should produce, removed beforehand so a stale one
+// cannot pass for a fresh build
+fn prepare_compile(input: &str, cwd: &std::path::Path) -> std::path::PathBuf {
     let args: Vec<&str> = input.split_whitespace().collect();
     if args.len() < 5 {
         panic!("[!] Invalid gcc input: {}", input);
     }
 
-    let elf_path = cwd.join(args.last().as_ref().unwrap());
+    let elf_path = cwd.join(compile_output_path(&args));
     if elf_path.exists() {
-        std::fs::remove_file(elf_path)
+        std::fs::remove_file(&elf_path)
             .expect("[-] Failed to remove existing executable");
     }
+    elf_path
+}
 
-    let output = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(input)
-        .current_dir(cwd)
-        .output()
-        .expect("[-] Failed to run compilation command");
+const COMPILE_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
-    let log_path = cwd.join("compilation_output.txt");
-    let mut logfile = std::fs::File::create(&log_path).expect(
-        format!("[-] Failed to create compilation log file: {:?}", &log_path)
-            .as_str(),
-    );
+fn finish_compile(
+    output: &std::process::Output,
+    timed_out: Option<Duration>,
+    elf_path: &std::path::Path,
+    patterns: &CompilePatterns,
+    log_path: &std::path::Path,
+) -> CompileResult {
+    let mut logfile = std::fs::File::create(log_path).unwrap_or_else(|_| {
+        panic!("[-] Failed to create compilation log file: {:?}", log_path)
+    });
 
     logfile
         .write_all(&output.stdout)
@@ -174,40 +1401,592 @@ pub fn compile(input: &str, cwd: &std::path::PathBuf) -> String {
         .write_all(&output.stderr)
         .expect("[-] Failed to write to compilation log file");
 
-    let needle = b"error:";
-    if let Some(_) = output
-        .stderr
-        .windows(needle.len())
-        .position(|window| window == needle)
-    {
-        return String::from("error");
+    if let Some(timeout) = timed_out {
+        writeln!(logfile, "[-] Compilation timed out after {:?}", timeout)
+            .expect("[-] Failed to write to compilation log file");
+        return CompileResult::Timeout;
     }
 
-    let needle = b"warning";
-    if let Some(_) = output
-        .stderr
-        .windows(needle.len())
-        .position(|window| window == needle)
-    {
-        return String::from("warning");
+    // colorized diagnostics interleave escape codes with the text
+    let stderr = strip_ansi(&output.stderr);
+    if CompilePatterns::matches(&patterns.errors, &stderr) {
+        return CompileResult::Error;
+    }
+
+    // the toolchain may exit cleanly yet produce nothing (e.g. a linker
+    // quirk), which would otherwise only surface when the tests run
+    if !is_executable(elf_path) {
+        writeln!(
+            logfile,
+            "[-] Expected output {:?} was not produced",
+            elf_path
+        )
+        .expect("[-] Failed to write to compilation log file");
+        return CompileResult::Error;
+    }
+
+    if CompilePatterns::matches(&patterns.warnings, &stderr) {
+        return CompileResult::Warning;
     }
 
-    String::from("success")
+    CompileResult::Success
+}
+
+pub fn default_valgrind_args() -> Vec<String> {
+    vec![
+        String::from("--leak-check=full"),
+        String::from("--tool=memcheck"),
+        String::from("--show-leak-kinds=all"),
+        String::from("--track-origins=yes"),
+        String::from("--error-exitcode=1"),
+    ]
 }
 
 pub fn check_valgrind_leaks(log_path: &std::path::PathBuf) -> bool {
-    let log_contents = match std::fs::read_to_string(log_path) {
-        Ok(contents) => contents,
+    let log_file = match std::fs::File::open(log_path) {
+        Ok(file) => file,
         Err(_) => {
             println!("[-] Failed to read valgrind log file");
             return false;
         }
     };
 
+    // scan line by line so memory stays bounded for huge verbose logs, up to
+    // the clean summary
     let needle = "ERROR SUMMARY: 0 errors from 0 contexts";
-    if log_contents.contains(needle) {
-        true
-    } else {
+    for line in std::io::BufReader::new(log_file).lines() {
+        match line {
+            Ok(line) if line.contains(needle) => return true,
+            Ok(_) => continue,
+            Err(_) => {
+                println!("[-] Failed to read valgrind log file");
+                return false;
+            }
+        }
+    }
+
+    false
+}
+
+// the error kind (e.g. "heap-use-after-free") from the first sanitizer
+// report banner in stderr, LeakSanitizer reports included
+pub fn detect_asan_error(stderr: &[u8]) -> Option<String> {
+    let stderr = String::from_utf8_lossy(&strip_ansi(stderr)).into_owned();
+    stderr.lines().find_map(|line| {
+        let (_, report) = line.split_once("ERROR: ")?;
+        let kind = report
+            .strip_prefix("AddressSanitizer: ")
+            .or_else(|| report.strip_prefix("LeakSanitizer: "))?;
+        match kind.split_whitespace().next()? {
+            // "LeakSanitizer: detected memory leaks"
+            "detected" => Some(String::from("memory-leak")),
+            kind => Some(kind.to_string()),
+        }
+    })
+}
+
+// descriptors reported open at exit by `--track-fds=yes`, the highest count
+// when several processes were traced
+pub fn valgrind_open_fds(log: &str) -> Option<usize> {
+    log.lines()
+        .filter_map(|line| line.split("FILE DESCRIPTORS: ").nth(1))
+        .filter_map(|rest| rest.split_whitespace().next()?.parse().ok())
+        .max()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeakKind {
+    DefinitelyLost,
+    IndirectlyLost,
+    PossiblyLost,
+    StillReachable,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValgrindSummary {
+    pub errors: u64,
+    pub contexts: u64,
+    // bytes reported under each leak kind of the LEAK SUMMARY
+    pub definitely_lost: u64,
+    pub indirectly_lost: u64,
+    pub possibly_lost: u64,
+    pub still_reachable: u64,
+    // the HEAP SUMMARY's "total heap usage" line
+    pub allocs: u64,
+    pub frees: u64,
+    pub bytes_allocated: u64,
+}
+
+pub struct LeakPolicy {
+    pub fail_on: Vec<LeakKind>,
+    pub fail_on_errors: bool,
+}
+
+// valgrind prints counts with thousands separators, e.g. "1,024 bytes"
+fn parse_count(text: &str) -> u64 {
+    let token = text.split_whitespace().next().unwrap_or("0");
+    token.replace(',', "").parse().unwrap_or(0)
+}
+
+impl ValgrindSummary {
+    pub fn parse(log: &str) -> Option<Self> {
+        Self::from_reader(log.as_bytes()).unwrap_or(None)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> std::io::Result<Option<Self>> {
+        let mut summary = Self::default();
+
+        // the ERROR SUMMARY comes last, after the leak summary
+        for line in reader.lines() {
+            if summary.parse_line(&line?) {
+                return Ok(Some(summary));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // returns true once the line holding the ERROR SUMMARY is parsed
+    fn parse_line(&mut self, line: &str) -> bool {
+        let leak_kinds = [
+            ("definitely lost:", &mut self.definitely_lost),
+            ("indirectly lost:", &mut self.indirectly_lost),
+            ("possibly lost:", &mut self.possibly_lost),
+            ("still reachable:", &mut self.still_reachable),
+        ];
+
+        for (needle, bytes) in leak_kinds {
+            if let Some(pos) = line.find(needle) {
+                *bytes = parse_count(&line[pos + needle.len()..]);
+                return false;
+            }
+        }
+
+        let needle = "total heap usage:";
+        if let Some(pos) = line.find(needle) {
+            // total heap usage: 3 allocs, 3 frees, 1,048 bytes allocated
+            let words: Vec<&str> =
+                line[pos + needle.len()..].split_whitespace().collect();
+            for pair in words.windows(2) {
+                let count = parse_count(pair[0]);
+                match pair[1].trim_end_matches(',') {
+                    "allocs" => self.allocs = count,
+                    "frees" => self.frees = count,
+                    "bytes" => self.bytes_allocated = count,
+                    _ => {}
+                }
+            }
+            return false;
+        }
+
+        let needle = "ERROR SUMMARY:";
+        if let Some(pos) = line.find(needle) {
+            // ERROR SUMMARY: 3 errors from 2 contexts (suppressed: 0 from 0)
+            let rest = &line[pos + needle.len()..];
+            self.errors = parse_count(rest);
+            if let Some(pos) = rest.find("from") {
+                self.contexts = parse_count(&rest[pos + "from".len()..]);
+            }
+            return true;
+        }
+
         false
     }
+
+    pub fn lost_bytes(&self, kind: LeakKind) -> u64 {
+        match kind {
+            LeakKind::DefinitelyLost => self.definitely_lost,
+            LeakKind::IndirectlyLost => self.indirectly_lost,
+            LeakKind::PossiblyLost => self.possibly_lost,
+            LeakKind::StillReachable => self.still_reachable,
+        }
+    }
+}
+
+impl LeakPolicy {
+    pub fn strict() -> Self {
+        Self {
+            fail_on: vec![
+                LeakKind::DefinitelyLost,
+                LeakKind::IndirectlyLost,
+                LeakKind::PossiblyLost,
+                LeakKind::StillReachable,
+            ],
+            fail_on_errors: true,
+        }
+    }
+
+    // tolerates memory that is still reachable at exit
+    pub fn lenient() -> Self {
+        Self {
+            fail_on: vec![
+                LeakKind::DefinitelyLost,
+                LeakKind::IndirectlyLost,
+                LeakKind::PossiblyLost,
+            ],
+            fail_on_errors: true,
+        }
+    }
+
+    pub fn allows(&self, summary: &ValgrindSummary) -> bool {
+        if self.fail_on_errors && summary.errors > 0 {
+            return false;
+        }
+
+        self.fail_on
+            .iter()
+            .all(|kind| summary.lost_bytes(*kind) == 0)
+    }
+}
+
+pub fn check_valgrind_policy(
+    log_path: &std::path::PathBuf,
+    policy: &LeakPolicy,
+) -> bool {
+    let summary = std::fs::File::open(log_path).and_then(|file| {
+        ValgrindSummary::from_reader(std::io::BufReader::new(file))
+    });
+
+    match summary {
+        Ok(Some(summary)) => policy.allows(&summary),
+        Ok(None) => {
+            println!("[-] Valgrind log file has no error summary");
+            false
+        }
+        Err(_) => {
+            println!("[-] Failed to read valgrind log file");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // the tail of a memcheck log for a program that freed all but 72 bytes
+    const STILL_REACHABLE_LOG: &str = "\
+==1== HEAP SUMMARY:
+==1==     in use at exit: 72 bytes in 1 blocks
+==1==   total heap usage: 2 allocs, 1 frees, 1,096 bytes allocated
+==1==
+==1== LEAK SUMMARY:
+==1==    definitely lost: 0 bytes in 0 blocks
+==1==    indirectly lost: 0 bytes in 0 blocks
+==1==      possibly lost: 0 bytes in 0 blocks
+==1==    still reachable: 72 bytes in 1 blocks
+==1==         suppressed: 0 bytes in 0 blocks
+==1==
+==1== ERROR SUMMARY: 0 errors from 0 contexts (suppressed: 0 from 0)
+";
+
+    #[test]
+    fn valgrind_summary_parses_the_log() {
+        let summary = ValgrindSummary::parse(STILL_REACHABLE_LOG).unwrap();
+        assert_eq!(
+            summary,
+            ValgrindSummary {
+                still_reachable: 72,
+                allocs: 2,
+                frees: 1,
+                bytes_allocated: 1096,
+                ..ValgrindSummary::default()
+            }
+        );
+
+        let errors = "==1== ERROR SUMMARY: 3 errors from 2 contexts";
+        let summary = ValgrindSummary::parse(errors).unwrap();
+        assert_eq!((summary.errors, summary.contexts), (3, 2));
+        assert_eq!(ValgrindSummary::parse("==1== HEAP SUMMARY:"), None);
+    }
+
+    #[test]
+    fn check_valgrind_leaks_scans_large_logs() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let noise = "==1== at 0x4848899: malloc (vg_replace_malloc.c:381)\n";
+        let noise = noise.repeat(80_000);
+
+        let clean = dir.join("clean.log");
+        let summary = "==1== ERROR SUMMARY: 0 errors from 0 contexts";
+        std::fs::write(&clean, format!("{}{}\n", noise, summary)).unwrap();
+        let started = std::time::Instant::now();
+        assert!(check_valgrind_leaks(&clean));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let leaking = dir.join("leaking.log");
+        let summary = "==1== ERROR SUMMARY: 1 errors from 1 contexts";
+        std::fs::write(&leaking, format!("{}{}\n", noise, summary)).unwrap();
+        assert!(!check_valgrind_leaks(&leaking));
+        assert!(!check_valgrind_leaks(&dir.join("missing.log")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn valgrind_open_fds_reads_the_largest_count() {
+        let log = "==1== FILE DESCRIPTORS: 3 open (3 std) at exit.\n\
+                   ==2== FILE DESCRIPTORS: 7 open (3 std) at exit.\n";
+        assert_eq!(valgrind_open_fds(log), Some(7));
+        assert_eq!(valgrind_open_fds("==1== HEAP SUMMARY:"), None);
+    }
+
+    #[test]
+    fn leak_policies_differ_on_still_reachable_memory() {
+        let summary = ValgrindSummary::parse(STILL_REACHABLE_LOG).unwrap();
+        assert!(LeakPolicy::lenient().allows(&summary));
+        assert!(!LeakPolicy::strict().allows(&summary));
+
+        let lost = ValgrindSummary { definitely_lost: 8, ..summary };
+        assert!(!LeakPolicy::lenient().allows(&lost));
+    }
+
+    #[test]
+    fn invalid_utf8_decodes_per_encoding() {
+        let output = ProcessOutput::new(
+            b"caf\xe9\n".to_vec(),
+            b"ok".to_vec(),
+            Ok(ExitStatus::from_raw(0)),
+        );
+        assert_eq!(output.stdout_str(), "caf\u{fffd}\n");
+        assert!(output.stdout_strict().is_err());
+        assert!(output.stdout_as(Encoding::Utf8).is_err());
+        assert_eq!(output.stdout_as(Encoding::Latin1).unwrap(), "caf\u{e9}\n");
+        assert_eq!(output.stderr_strict().unwrap(), "ok");
+    }
+
+    #[test]
+    fn detect_asan_error_reads_the_report_kind() {
+        let report = b"=================================================\n\
+            ==7==ERROR: AddressSanitizer: heap-use-after-free on address 0x6\n\
+            READ of size 4 at 0x6 thread T0\n";
+        let leaks = b"\x1b[1m\x1b[31m==7==ERROR: LeakSanitizer: detected \
+            memory leaks\x1b[0m\n";
+        assert_eq!(
+            detect_asan_error(report).as_deref(),
+            Some("heap-use-after-free")
+        );
+        assert_eq!(detect_asan_error(leaks).as_deref(), Some("memory-leak"));
+        assert_eq!(detect_asan_error(b"ERROR: file not found\n"), None);
+    }
+
+    #[test]
+    fn compile_output_path_follows_dash_o() {
+        let path = |cmd: &str| {
+            let args: Vec<&str> = cmd.split_whitespace().collect();
+            compile_output_path(&args).to_string()
+        };
+        assert_eq!(path("gcc -o prog main.c -Wall"), "prog");
+        assert_eq!(path("gcc -oprog main.c"), "prog");
+        assert_eq!(path("gcc main.c out"), "out");
+    }
+
+    #[test]
+    fn compile_without_a_binary_is_an_error() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // exits cleanly without producing ./prog
+        let timeout = Duration::from_secs(60);
+        let input = "true gcc -o prog main.c";
+        let result = compile_with_timeout(input, &dir, timeout);
+        assert_eq!(result, CompileResult::Error);
+        let builds = "touch prog && chmod +x prog # -o prog";
+        assert_eq!(compile(builds, &dir), "success");
+        let log = std::fs::read_to_string(dir.join("compilation_output.txt"));
+        assert_eq!(log.unwrap(), "");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn args(cmd: &str) -> Vec<String> {
+        cmd.split_whitespace().map(String::from).collect()
+    }
+
+    #[tokio::test]
+    async fn any_read_buffer_size_reads_the_same_bytes() {
+        let output: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        for read_buffer in [0, 1, 7, 4096, DEFAULT_READ_BUFFER, 1 << 20] {
+            let pipe = output.as_slice();
+            let read = read_pipe(pipe, None, Vec::new(), false, read_buffer);
+            assert_eq!(read.await, (output.clone(), output.len()));
+        }
+
+        let discarded = read_pipe(output.as_slice(), None, Vec::new(), true, 7);
+        assert_eq!(discarded.await, (Vec::new(), output.len()));
+    }
+
+    #[test]
+    fn a_log_budget_takes_only_what_remains() {
+        let budget = LogBudget::new(100);
+        assert!(budget.take(60));
+        assert!(!budget.take(41));
+        assert_eq!(budget.remaining(), 40);
+        assert!(budget.take(40));
+        assert!(!budget.take(1));
+        assert!(budget.take(0));
+    }
+
+    #[tokio::test]
+    async fn pipe_line_reader_yields_lines_in_order() {
+        let output: &'static [u8] = b"first\nsecond\r\n\npartial";
+        let mut lines = pipe_line_reader(output);
+        let mut received = Vec::new();
+        while let Some(line) = lines.recv().await {
+            received.push(line);
+        }
+        assert_eq!(received, ["first", "second", "", "partial"]);
+    }
+
+    #[tokio::test]
+    async fn the_tee_logs_everything_and_keeps_the_tail() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("output.txt");
+        let tee = OutputTee::create(&log, 1024).await.unwrap();
+
+        let cmd = args("head -c 5000000 /dev/zero");
+        let mut child = TestSpawner::with_tee(&cmd, &dir, 0, None, tee).await;
+        let output = child.as_mut().unwrap().wait(10).await;
+        assert!(output.stdout.len() <= 1024);
+        assert_eq!(output.stdout_len, 5_000_000);
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), 5_000_000);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_with_timeout_reports_timeouts_and_spawn_failures() {
+        let cwd = PathBuf::from(".");
+        let output = run_with_timeout(&args("sleep 30"), &cwd, 1).await;
+        assert!(output.truncated_by_timeout);
+        assert_eq!(output.status.unwrap().into_raw(), Status::Timeout as i32);
+
+        let output = run_with_timeout(&args("./no-such-prog"), &cwd, 1).await;
+        let error = output.status.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn a_zero_timeout_waits_for_the_child() {
+        let cwd = PathBuf::from(".");
+        let mut child = TestSpawner::new(&args("sleep 1"), &cwd, 0).await;
+        let output = child.as_mut().unwrap().wait(0).await;
+        assert!(output.status.unwrap().success());
+        assert!(!output.truncated_by_timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_run_on_paused_time() {
+        let cwd = PathBuf::from(".");
+        let started = std::time::Instant::now();
+        let mut child = TestSpawner::new(&args("sleep 30"), &cwd, 0).await;
+        let output = child.as_mut().unwrap().wait(10).await;
+        assert!(output.truncated_by_timeout);
+        assert_eq!(output.status.unwrap().into_raw(), Status::Timeout as i32);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn commands_resolve_from_absolute_paths_and_path() {
+        let cwd = PathBuf::from(".");
+        for cmd in ["/bin/echo hi", "echo hi"] {
+            let mut child = TestSpawner::new(&args(cmd), &cwd, 0).await;
+            let output = child.as_mut().unwrap().wait(5).await;
+            assert_eq!(output.stdout, b"hi\n");
+        }
+
+        let missing = TestSpawner::new(&args("./no-such-prog"), &cwd, 0).await;
+        let error = missing.err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn compile_async_leaves_the_runtime_running() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = "int main(void) { return 0; }\n";
+        std::fs::write(dir.join("main.c"), source).unwrap();
+
+        // ticks on the same thread only while the compile yields
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let ticks = Arc::clone(&ticks);
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        let timeout = Duration::from_secs(60);
+        let compiled =
+            compile_async("gcc -Wall main.c -o main", &dir, timeout).await;
+        ticker.abort();
+
+        assert_eq!(compiled, CompileResult::Success);
+        assert!(dir.join("main").exists());
+        assert!(ticks.load(Ordering::Relaxed) > 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_compile_past_its_timeout_is_killed() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let hangs = "sleep 30 # -o prog";
+        let timeout = Duration::from_millis(500);
+        let patterns = CompilePatterns::default();
+
+        let started = std::time::Instant::now();
+        let result = compile_with_patterns(hangs, &dir, &patterns, timeout);
+        assert_eq!(result, CompileResult::Timeout);
+        let result = compile_with_timeout(hangs, &dir, timeout);
+        assert_eq!(result, CompileResult::Timeout);
+        let result = compile_async(hangs, &dir, timeout).await;
+        assert_eq!(result, CompileResult::Timeout);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(result.failed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compile_detects_colored_and_custom_diagnostics() {
+        let dir = crate::unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let builds = "touch prog && chmod +x prog";
+
+        let colored = format!(
+            "printf '\\033[1;31merr\\033[0mor: bad\\n' >&2; {} # -o prog",
+            builds
+        );
+        assert_eq!(compile(&colored, &dir), "error");
+
+        let german = format!("echo Fehler: kaputt >&2; {} # -o prog", builds);
+        let patterns = CompilePatterns {
+            errors: vec![String::from("Fehler:")],
+            warnings: vec![String::from("Warnung:")],
+        };
+        assert_eq!(compile(&german, &dir), "success");
+        let timeout = Duration::from_secs(60);
+        let result = compile_with_patterns(&german, &dir, &patterns, timeout);
+        assert_eq!(result, CompileResult::Error);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_checker_reports_a_missing_checker() {
+        let cmd = [String::from("./no-such-checker")];
+        let output = run_checker(&cmd, b"", &".".into(), 5).await;
+        assert!(output.status.is_err());
+
+        let output = run_checker(&[], b"", &".".into(), 5).await;
+        assert!(output.status.is_err());
+    }
+
+    #[tokio::test]
+    async fn run_checker_pipes_stdin() {
+        let cmd = [String::from("cat")];
+        let output = run_checker(&cmd, b"verdict\n", &".".into(), 5).await;
+        assert_eq!(output.stdout, b"verdict\n");
+        assert!(output.status.unwrap().success());
+    }
 }
@@ -1,21 +1,121 @@
 use super::run::*;
-use crate::ProcessOutput;
+use crate::report::archive::{ArchivedTest, RunArchive};
+use crate::{
+    child_processes, compare_lines_unordered, copy_recursively, excerpt_lines,
+    expand_env_vars, expand_placeholders, is_valid_elf, line_diff,
+    line_diff_visible, open_tcp_inodes, resolve_executable, strip_prompt,
+    unique_temp_dir, ChildProcess, DirSnapshot, Error, ProcessOutput,
+};
 use async_trait::async_trait;
 use indexmap::IndexMap;
-use libc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::RangeInclusive;
 use std::os::unix::process::ExitStatusExt;
-use std::path::PathBuf;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
+pub const DEFAULT_SUITE: &str = "default";
+
+// how long a communicator may keep going once the process has exited
+const COMMUNICATE_GRACE: Duration = Duration::from_millis(500);
+// past the longest timeout, covers the timeout signal and communicate graces
+const RUN_DEADLINE_SLACK: Duration = Duration::from_secs(5);
+
+// aborts the task when dropped, e.g. when a run is cut short by its deadline
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Termination {
+    Exit(RangeInclusive<i32>),
+    Signal(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Runner {
+    // a compiled executable named by the args template
+    Native,
+    // an interpreter command prepended to the args template, whose first
+    // argument is then the script to run
+    Interpreter(String),
+}
+
+pub type OutputNormalizer = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+#[derive(Debug, Default)]
 pub struct CommunicateOutput {
     pub output: Vec<Vec<u8>>,
     pub error: Option<std::io::Error>,
+    // bytes per second, see Communicator::measure_throughput
+    pub throughput: Option<f64>,
+    // parsed replies, see Communicator::request_json
+    pub responses: Vec<serde_json::Value>,
+}
+
+impl Clone for CommunicateOutput {
+    fn clone(&self) -> Self {
+        Self {
+            output: self.output.clone(),
+            error: self
+                .error
+                .as_ref()
+                .map(|e| std::io::Error::new(e.kind(), e.to_string())),
+            throughput: self.throughput,
+            responses: self.responses.clone(),
+        }
+    }
 }
 
-#[allow(unused_variables)]
+// what the session knows beyond the run itself
+#[derive(Debug, Default)]
+pub struct TestContext {
+    // the latest TestManager::compile_assignment, if any
+    pub compile_report: Option<CompileReport>,
+    // filled in by the agent while validating, see record
+    metadata: std::sync::Mutex<HashMap<String, String>>,
+}
+
+impl Clone for TestContext {
+    fn clone(&self) -> Self {
+        Self {
+            compile_report: self.compile_report.clone(),
+            metadata: std::sync::Mutex::new(self.metadata()),
+        }
+    }
+}
+
+impl TestContext {
+    // surfaced on the outcome and in the reports, e.g. a score breakdown
+    // or a measured latency; a repeated key keeps the last value
+    pub fn record(&self, key: &str, value: impl ToString) {
+        let mut metadata = self.metadata.lock().unwrap();
+        metadata.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn metadata(&self) -> HashMap<String, String> {
+        self.metadata.lock().unwrap().clone()
+    }
+
+    fn take_metadata(&self) -> HashMap<String, String> {
+        std::mem::take(&mut *self.metadata.lock().unwrap())
+    }
+}
+
+#[allow(unused_variables, clippy::ptr_arg)]
 #[async_trait]
 pub trait TestAgent: Send + Sync {
     async fn validate(
@@ -28,6 +128,19 @@ pub trait TestAgent: Send + Sync {
         unimplemented!("Must be implemented by the type")
     }
 
+    // called instead of validate for single-client tests, override it to
+    // inspect the context
+    async fn validate_with_context(
+        &self,
+        context: &TestContext,
+        args: &Vec<String>,
+        communicate_output: Option<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        self.validate(args, communicate_output, output, cwd).await
+    }
+
     async fn communicate(
         &self,
         read_timeout: u64,
@@ -36,48 +149,356 @@ pub trait TestAgent: Send + Sync {
     ) -> CommunicateOutput {
         unimplemented!("Must be implemented by the type")
     }
+
+    // called instead of validate when the template runs several
+    // concurrent clients, with one output per client; by default every
+    // client output must pass validate
+    async fn validate_clients(
+        &self,
+        args: &Vec<String>,
+        communicate_outputs: Vec<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        for communicate_output in communicate_outputs {
+            let valid = self
+                .validate(args, Some(communicate_output), output.clone(), cwd)
+                .await;
+            if !valid {
+                return false;
+            }
+        }
+        true
+    }
+
+    // called instead of validate_clients, override it to inspect the
+    // context
+    async fn validate_clients_with_context(
+        &self,
+        context: &TestContext,
+        args: &Vec<String>,
+        communicate_outputs: Vec<CommunicateOutput>,
+        output: ProcessOutput,
+        cwd: &std::path::PathBuf,
+    ) -> bool {
+        self.validate_clients(args, communicate_outputs, output, cwd)
+            .await
+    }
+
+    // recorded as the outcome's reason when the test fails, read after
+    // validation
+    fn failure_reason(&self) -> Option<String> {
+        None
+    }
+
+    // async cleanup of what communicate opened (connections, temporary
+    // files), awaited once the run has ended, after validation or when the
+    // run overran its deadline
+    async fn finalize(&self) {}
 }
 
 pub struct TestTemplateBuilder {
     // pass through new method
     name: String,
+    suite: String,
+    description: String,
     cmd_args_template: String,
+    literal_braces: Vec<String>,
+    pipe_from: Vec<String>,
+    pipe_into: Vec<String>,
+    runner: Runner,
+    preserved_paths: Vec<PathBuf>,
     test_factory: Option<Box<dyn Fn() -> Box<dyn TestAgent>>>,
-    timeout: u64,
+    timeout: Option<u64>,
+    soft_timeout: Option<u64>,
+    timeout_signal: Option<i32>,
+    nice: Option<i32>,
+    env: Vec<(String, String)>,
+    input_grace: Option<Duration>,
+    stdin_script: Option<StdinScript>,
+    process_group: bool,
+    strict_env: bool,
+    pty: bool,
+    discard_stdout: bool,
+    discard_stderr: bool,
+    read_buffer: Option<usize>,
     // validator builder attributes
+    expected_termination: Option<Termination>,
+    normalizer: Option<OutputNormalizer>,
+    prompt: Option<String>,
+    expected_stdout: Option<Vec<u8>>,
+    expected_stderr: Option<Vec<u8>>,
+    expected_stderr_file: Option<PathBuf>,
+    ignore_line_order: bool,
+    verbose_diff: bool,
     valgrind: bool,
+    valgrind_args: Option<Vec<String>>,
+    valgrind_suppressions: Option<PathBuf>,
+    valgrind_verbose: bool,
+    detect_asan: bool,
     log_output: bool,
+    output_tail: Option<usize>,
+    report_output_lines: Option<usize>,
+    fail_on_stdout: Option<String>,
+    done_on_stdout: Option<String>,
+    fail_on_stderr: Option<String>,
+    max_open_fds: Option<usize>,
+    detect_socket_leaks: bool,
+    max_rss: Option<u64>,
+    max_allocs: Option<u64>,
+    stdout_bytes: (Option<usize>, Option<usize>),
+    xfail: bool,
+    points: f64,
+    isolated: bool,
+    smoke: bool,
+    dependencies: Vec<String>,
+    artifact_only: bool,
+    require_elf: bool,
     // communicator builder attributes
     communicate: bool,
     operation_timeout: u64,
+    clients: usize,
 }
 
 pub struct TestTemplate {
     name: String,
+    suite: String,
+    description: String,
     cmd_args_template: String,
+    literal_braces: Vec<String>,
+    pipe_from: Vec<String>,
+    pipe_into: Vec<String>,
+    runner: Runner,
+    preserved_paths: Vec<PathBuf>,
     test_factory: Box<dyn Fn() -> Box<dyn TestAgent>>,
-    timeout: u64,
+    timeout: Option<u64>,
+    soft_timeout: Option<u64>,
+    timeout_signal: Option<i32>,
+    nice: Option<i32>,
+    env: Vec<(String, String)>,
+    input_grace: Option<Duration>,
+    stdin_script: Option<StdinScript>,
+    process_group: bool,
+    strict_env: bool,
+    pty: bool,
+    discard_stdout: bool,
+    discard_stderr: bool,
+    read_buffer: Option<usize>,
+    expected_termination: Option<Termination>,
+    normalizer: Option<OutputNormalizer>,
+    prompt: Option<String>,
+    expected_stdout: Option<Vec<u8>>,
+    expected_stderr: Option<Vec<u8>>,
+    expected_stderr_file: Option<PathBuf>,
+    ignore_line_order: bool,
+    verbose_diff: bool,
     valgrind: bool,
+    valgrind_args: Option<Vec<String>>,
+    valgrind_suppressions: Option<PathBuf>,
+    valgrind_verbose: bool,
+    detect_asan: bool,
     log_output: bool,
+    output_tail: Option<usize>,
+    report_output_lines: Option<usize>,
+    fail_on_stdout: Option<String>,
+    done_on_stdout: Option<String>,
+    fail_on_stderr: Option<String>,
+    max_open_fds: Option<usize>,
+    detect_socket_leaks: bool,
+    max_rss: Option<u64>,
+    max_allocs: Option<u64>,
+    stdout_bytes: (Option<usize>, Option<usize>),
+    xfail: bool,
+    points: f64,
+    isolated: bool,
+    smoke: bool,
+    dependencies: Vec<String>,
+    require_elf: bool,
     require_communicator: bool,
     operation_timeout: u64,
+    clients: usize,
 }
 
 pub struct Test {
     name: String,
+    suite: String,
+    description: String,
     cmd_args: Vec<String>,
+    // pipeline stages around the program, templated like its args
+    pipe_from: Vec<Vec<String>>,
+    pipe_into: Vec<Vec<String>>,
+    script: Option<String>,
+    // the native program checked by require_elf
+    binary: Option<String>,
+    results_dir: Option<PathBuf>,
+    valgrind_error_code: Option<i32>,
+    valgrind_log: Option<PathBuf>,
+    detect_asan: bool,
+    preserved_paths: Vec<PathBuf>,
     test: Box<dyn TestAgent>,
     timeout: u64,
+    soft_timeout: Option<u64>,
+    timeout_signal: Option<i32>,
+    nice: Option<i32>,
+    env: Vec<(String, String)>,
+    input_grace: Option<Duration>,
+    stdin_script: Option<StdinScript>,
+    process_group: bool,
+    pty: bool,
+    discard_stdout: bool,
+    discard_stderr: bool,
+    read_buffer: Option<usize>,
+    stdin: Option<Vec<u8>>,
+    expected_stdout: Option<Vec<u8>>,
+    expected_stderr: Option<Vec<u8>>,
+    expected_stderr_file: Option<PathBuf>,
+    ignore_line_order: bool,
+    verbose_diff: bool,
+    context: TestContext,
+    expected_termination: Option<Termination>,
+    normalizer: Option<OutputNormalizer>,
+    prompt: Option<String>,
     log_output: bool,
+    output_tail: Option<usize>,
+    report_output_lines: Option<usize>,
+    fail_on_stdout: Option<String>,
+    done_on_stdout: Option<String>,
+    fail_on_stderr: Option<String>,
+    max_open_fds: Option<usize>,
+    detect_socket_leaks: bool,
+    max_rss: Option<u64>,
+    max_allocs: Option<u64>,
+    stdout_bytes: (Option<usize>, Option<usize>),
+    xfail: bool,
+    points: f64,
+    isolated: bool,
+    smoke: bool,
+    dependencies: Vec<String>,
     require_communicator: bool,
     operation_timeout: u64,
+    clients: usize,
     port: u16,
+    // armed with Some by record_run, then holds the captured stdout and
+    // stderr of the last run
+    recorded: std::sync::Mutex<Option<(Vec<u8>, Vec<u8>)>>,
+    // shared with the other tests of the manager, see with_log_budget
+    log_budget: Option<Arc<LogBudget>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+    // failed as expected by an xfail test
+    XFail,
+    // xfail test that unexpectedly passed
+    XPass,
+    // the run broke down before a verdict, e.g. past its overall deadline
+    Errored,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestOutcome {
+    pub suite: String,
+    pub description: String,
+    pub status: TestStatus,
+    // None when killed by a signal or never run
+    pub exit_code: Option<i32>,
+    // earned, the template's points when it passed and 0 otherwise
+    pub points: f64,
+    pub duration: Duration,
+    // ran longer than the test's soft timeout
+    pub slow: bool,
+    pub stdout_bytes: usize,
+    pub stdout_lines: usize,
+    pub stderr_bytes: usize,
+    pub stderr_lines: usize,
+    // sampled while running, None when the process exited before a sample
+    pub peak_rss_bytes: Option<u64>,
+    // from valgrind's heap summary, None without valgrind
+    pub allocs: Option<u64>,
+    // the process was killed on timeout, see ProcessOutput
+    pub truncated_by_timeout: bool,
+    // see TestTemplateBuilder::detect_socket_leaks
+    pub socket_leak_detected: bool,
+    // per pipeline stage in order, None for a stage killed by a signal or
+    // not started; empty without a pipeline
+    pub stage_exit_codes: Vec<Option<i32>>,
+    pub timings: PhaseTimings,
+    // recorded by the agent through TestContext::record
+    pub metadata: HashMap<String, String>,
+    // head and tail of the output, see report_output_lines
+    pub stdout_excerpt: Option<String>,
+    pub stderr_excerpt: Option<String>,
+    // why the test was skipped, or failed when its agent says, see
+    // TestAgent::failure_reason
+    pub reason: Option<String>,
+}
+
+// where a run spent its time, zero for the phases it did not reach
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    // of the assignment, shared by its tests, None when not compiled
+    pub compile: Option<Duration>,
+    // starting the process
+    pub spawn: Duration,
+    // until the process was started and the startup delay had passed
+    pub ready: Duration,
+    pub communicate: Duration,
+    // from start to exit
+    pub process: Duration,
+    pub validate: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStats {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub xfailed: usize,
+    pub xpassed: usize,
+    pub errored: usize,
+    // passed over executed (non-skipped, non-xfail) tests, 0 when none were
+    // executed
+    pub pass_rate: f64,
+    pub total_duration: Duration,
+    pub mean_duration: Duration,
+    pub median_duration: Duration,
+}
+
+// template metadata for publishing, see TestManager::catalog
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    pub name: String,
+    pub suite: String,
+    pub description: String,
+    // seconds, the manager's default when the template sets none, 0 is none
+    pub timeout: u64,
+    pub soft_timeout: Option<u64>,
+    pub points: f64,
+    // the template's flags, of "valgrind", "communicate", "xfail" and
+    // "smoke"
+    pub tags: Vec<String>,
+}
+
+pub struct SuiteResults {
+    pub results: Vec<(String, TestOutcome)>,
 }
 
 pub struct TestManager<'a> {
     pub name: &'a str,
     pub tests_dir_path: PathBuf,
     startup_delay: u64,
+    default_timeout: u64,
+    results_dir: Option<PathBuf>,
+    compile_patterns: CompilePatterns,
+    compile_timeout: Duration,
+    compile_command: Option<String>,
+    batch_concurrency: usize,
+    compile_report: Option<CompileReport>,
+    log_budget: Option<Arc<LogBudget>>,
     templates: IndexMap<String, TestTemplate>,
     active_tests: IndexMap<String, Test>,
 }
@@ -86,30 +507,273 @@ impl TestTemplateBuilder {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            suite: String::from(DEFAULT_SUITE),
+            description: String::new(),
             cmd_args_template: String::new(),
+            literal_braces: Vec::new(),
+            pipe_from: Vec::new(),
+            pipe_into: Vec::new(),
+            runner: Runner::Native,
+            preserved_paths: Vec::new(),
             test_factory: None,
-            timeout: 0,
+            timeout: None,
+            soft_timeout: None,
+            timeout_signal: None,
+            nice: None,
+            env: Vec::new(),
+            input_grace: None,
+            stdin_script: None,
+            process_group: false,
+            strict_env: false,
+            pty: false,
+            discard_stdout: false,
+            discard_stderr: false,
+            read_buffer: None,
             // validator builder attributes
+            expected_termination: None,
+            normalizer: None,
+            prompt: None,
+            expected_stdout: None,
+            expected_stderr: None,
+            expected_stderr_file: None,
+            ignore_line_order: false,
+            verbose_diff: false,
             valgrind: false,
+            valgrind_args: None,
+            valgrind_suppressions: None,
+            valgrind_verbose: false,
+            detect_asan: false,
             log_output: false,
+            output_tail: None,
+            report_output_lines: None,
+            fail_on_stdout: None,
+            done_on_stdout: None,
+            fail_on_stderr: None,
+            max_open_fds: None,
+            detect_socket_leaks: false,
+            max_rss: None,
+            max_allocs: None,
+            stdout_bytes: (None, None),
+            xfail: false,
+            points: 1.0,
+            isolated: false,
+            smoke: false,
+            dependencies: Vec::new(),
+            artifact_only: false,
+            require_elf: false,
             // communicator builder attributes
             communicate: false,
             operation_timeout: 0,
+            clients: 1,
         }
     }
 
+    pub fn suite(mut self, suite: &str) -> Self {
+        self.suite = suite.to_string();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
     pub fn args_template(mut self, cmd_args_template: &str) -> Self {
         self.cmd_args_template = cmd_args_template.to_string();
         self
     }
 
+    // keeps `{name}` in the args or a stage as written rather than as a
+    // placeholder to substitute, e.g. awk's '{print}'
+    pub fn literal_braces(mut self, name: &str) -> Self {
+        self.literal_braces.push(name.to_string());
+        self
+    }
+
+    // a stage whose stdout feeds the program's stdin, `./gen | ./prog`;
+    // repeated calls chain the stages in order. A fixture's stdin goes to
+    // the first stage, env and nice only to the program
+    pub fn pipe_from(mut self, stage_template: &str) -> Self {
+        self.pipe_from.push(stage_template.to_string());
+        self
+    }
+
+    // a stage reading the program's stdout, `./prog | ./check`, the last
+    // stage's output is the one validated
+    pub fn pipe_into(mut self, stage_template: &str) -> Self {
+        self.pipe_into.push(stage_template.to_string());
+        self
+    }
+
+    pub fn runner(mut self, runner: Runner) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    // files or directories, relative to the tests directory, that are
+    // restored after the test runs
+    pub fn preserve_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.preserved_paths = paths;
+        self
+    }
+
     pub fn agent(mut self, test: Box<dyn Fn() -> Box<dyn TestAgent>>) -> Self {
         self.test_factory = Some(test);
         self
     }
 
+    // a timeout of 0 waits for the test indefinitely, leaving it unset
+    // falls back to the manager's default timeout
     pub fn timeout(mut self, validator_timeout: u64) -> Self {
-        self.timeout = validator_timeout;
+        self.timeout = Some(validator_timeout);
+        self
+    }
+
+    // sent on timeout before resorting to SIGKILL, e.g. libc::SIGINT for
+    // programs that clean up on interrupt
+    pub fn timeout_signal(mut self, signal: i32) -> Self {
+        self.timeout_signal = Some(signal);
+        self
+    }
+
+    // runs the process at this niceness (-20 to 19, higher is lower
+    // priority), e.g. to keep a loaded grading server responsive
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    // sets an environment variable for the process, also used to expand
+    // `$NAME` in the args template before the process environment
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // makes an unknown `$NAME` in the args template an error instead of
+    // leaving it as written
+    pub fn strict_env(mut self, strict_env: bool) -> Self {
+        self.strict_env = strict_env;
+        self
+    }
+
+    // fails the test as blocked on input when the process is still running
+    // `grace` after its stdin was written and closed, telling a parser stuck
+    // waiting for more input from a slow one; without stdin it gets none
+    pub fn detect_blocked_input(mut self, grace: Duration) -> Self {
+        self.input_grace = Some(grace);
+        self
+    }
+
+    // drives an interactive session, stdin closes only after the last step
+    pub fn stdin_script(mut self, script: StdinScript) -> Self {
+        self.stdin_script = Some(script);
+        self
+    }
+
+    // runs the process in its own process group and kills the group once
+    // the process has ended, timeout included, so no forked children
+    // survive the test
+    pub fn process_group(mut self, process_group: bool) -> Self {
+        self.process_group = process_group;
+        self
+    }
+
+    // drains stdout without keeping it, for a chatty server whose output
+    // is irrelevant; the outcome still counts its bytes
+    pub fn discard_stdout(mut self, discard: bool) -> Self {
+        self.discard_stdout = discard;
+        self
+    }
+
+    pub fn discard_stderr(mut self, discard: bool) -> Self {
+        self.discard_stderr = discard;
+        self
+    }
+
+    // bytes asked of each read of the output pipes, 64 KiB by default
+    pub fn read_buffer_size(mut self, bytes: usize) -> Self {
+        self.read_buffer = Some(bytes);
+        self
+    }
+
+    // gives the process a pseudo-terminal as stdout, for programs that
+    // behave differently when isatty is true
+    pub fn pty(mut self, pty: bool) -> Self {
+        self.pty = pty;
+        self
+    }
+
+    // flags the outcome as slow past this many seconds without failing it
+    pub fn soft_timeout(mut self, soft_timeout: u64) -> Self {
+        self.soft_timeout = Some(soft_timeout);
+        self
+    }
+
+    // exit code and signal expectations are exclusive, the last one set wins
+    pub fn expected_exit_code(self, code: i32) -> Self {
+        self.expected_exit_codes(code..=code)
+    }
+
+    pub fn expected_exit_codes(mut self, codes: RangeInclusive<i32>) -> Self {
+        self.expected_termination = Some(Termination::Exit(codes));
+        self
+    }
+
+    pub fn expect_signal(mut self, signal: i32) -> Self {
+        self.expected_termination = Some(Termination::Signal(signal));
+        self
+    }
+
+    // rewrites stdout before it is handed to the agent, e.g. to mask
+    // timestamps or pids that would break a golden comparison
+    pub fn normalizer<F>(mut self, normalizer: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.normalizer = Some(Arc::new(normalizer));
+        self
+    }
+
+    // removed from stdout wherever it appears, before the normalizer runs,
+    // so only the responses of an interactive program are compared
+    pub fn strip_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    // the (normalized) stdout the program must print, checked before the
+    // agent runs
+    pub fn expected_stdout(mut self, expected: impl Into<Vec<u8>>) -> Self {
+        self.expected_stdout = Some(expected.into());
+        self
+    }
+
+    // the stderr the program must print, inline rather than in a golden
+    // file; takes precedence over expected_stderr_file
+    pub fn expected_stderr(mut self, expected: impl Into<Vec<u8>>) -> Self {
+        self.expected_stderr = Some(expected.into());
+        self
+    }
+
+    // a golden file, relative to the tests directory, holding the stderr
+    // the program must print; the normalizer and ignore_line_order apply
+    pub fn expected_stderr_file(mut self, path: PathBuf) -> Self {
+        self.expected_stderr_file = Some(path);
+        self
+    }
+
+    // compare the expected stdout line by line in any order
+    pub fn ignore_line_order(mut self, ignore_line_order: bool) -> Self {
+        self.ignore_line_order = ignore_line_order;
+        self
+    }
+
+    // prints a diff when the stdout does not match too, and renders tabs,
+    // carriage returns and line feeds visibly in both diffs, see
+    // line_diff_visible
+    pub fn verbose_diff(mut self, verbose_diff: bool) -> Self {
+        self.verbose_diff = verbose_diff;
         self
     }
 
@@ -118,11 +782,167 @@ impl TestTemplateBuilder {
         self
     }
 
+    // replaces the default valgrind options, the log file is always appended
+    pub fn valgrind_args(mut self, valgrind_args: Vec<String>) -> Self {
+        self.valgrind_args = Some(valgrind_args);
+        self
+    }
+
+    pub fn valgrind_suppressions(mut self, suppressions: PathBuf) -> Self {
+        self.valgrind_suppressions = Some(suppressions);
+        self
+    }
+
+    // verbose logs are large and the leak checks only need the summaries
+    pub fn valgrind_verbose(mut self, valgrind_verbose: bool) -> Self {
+        self.valgrind_verbose = valgrind_verbose;
+        self
+    }
+
+    // fails the test when stderr holds an AddressSanitizer report, for
+    // programs built with -fsanitize=address
+    pub fn detect_asan(mut self, detect_asan: bool) -> Self {
+        self.detect_asan = detect_asan;
+        self
+    }
+
     pub fn log_output(mut self, log_output: bool) -> Self {
         self.log_output = log_output;
         self
     }
 
+    // with log_output, stream the output to the log as it arrives and keep
+    // only its last `bytes` of stdout and of stderr for validation
+    pub fn output_tail(mut self, bytes: usize) -> Self {
+        self.output_tail = Some(bytes);
+        self
+    }
+
+    // embeds this many of the first and last lines of stdout and stderr in
+    // the outcome for reports, the log still gets all of it
+    pub fn report_output_lines(mut self, lines: usize) -> Self {
+        self.report_output_lines = Some(lines);
+        self
+    }
+
+    // kills the process and fails the test as soon as stdout contains
+    // `sentinel`, e.g. a fatal banner, rather than waiting for the timeout
+    pub fn fail_on_stdout_contains(
+        mut self,
+        sentinel: impl Into<String>,
+    ) -> Self {
+        self.fail_on_stdout = Some(sentinel.into());
+        self
+    }
+
+    // stops the process and validates as soon as stdout contains
+    // `sentinel`, e.g. a server's answer, rather than waiting for the
+    // timeout; the kill is then not held against the process, though an
+    // expected exit code or signal is still checked against it
+    pub fn done_on_stdout_contains(
+        mut self,
+        sentinel: impl Into<String>,
+    ) -> Self {
+        self.done_on_stdout = Some(sentinel.into());
+        self
+    }
+
+    pub fn fail_on_stderr_contains(
+        mut self,
+        sentinel: impl Into<String>,
+    ) -> Self {
+        self.fail_on_stderr = Some(sentinel.into());
+        self
+    }
+
+    // fails the test when the process is seen holding more descriptors open,
+    // stdin, stdout and stderr included
+    pub fn max_open_fds(mut self, max_open_fds: usize) -> Self {
+        self.max_open_fds = Some(max_open_fds);
+        self
+    }
+
+    // fails the test when the process leaves sockets open: still held when
+    // it was killed on timeout, or outliving it in a descendant; best-effort,
+    // the kernel closes what a normally exiting process leaves open
+    pub fn detect_socket_leaks(mut self, detect: bool) -> Self {
+        self.detect_socket_leaks = detect;
+        self
+    }
+
+    // fails the test when the process's peak resident memory exceeds
+    // `bytes`; under valgrind this is valgrind's own memory
+    pub fn max_rss(mut self, bytes: u64) -> Self {
+        self.max_rss = Some(bytes);
+        self
+    }
+
+    // fails the test when valgrind's heap summary reports more than
+    // `max_allocs` allocations, has no effect without valgrind
+    pub fn max_allocs(mut self, max_allocs: u64) -> Self {
+        self.max_allocs = Some(max_allocs);
+        self
+    }
+
+    // runs the process in a fresh copy of the tests directory, removed
+    // afterwards, so files it writes cannot affect other tests; logs go to
+    // the results directory, the tests directory without one
+    pub fn isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    // runs first under run_smoke_then_full, its dependencies should be
+    // smoke tests too
+    pub fn smoke(mut self, smoke: bool) -> Self {
+        self.smoke = smoke;
+        self
+    }
+
+    // skips the test unless the named test ran first and passed, e.g. when
+    // it consumes a file that test produces; may be called several times
+    pub fn depends_on(mut self, test_name: &str) -> Self {
+        self.dependencies.push(test_name.to_string());
+        self
+    }
+
+    // awarded to the outcome when the test passes, 1 by default
+    pub fn points(mut self, points: f64) -> Self {
+        self.points = points;
+        self
+    }
+
+    // fails the test when stdout is shorter than `min` or longer than `max`
+    // bytes, e.g. a submission printing nothing
+    pub fn expect_stdout_bytes(
+        mut self,
+        min: Option<usize>,
+        max: Option<usize>,
+    ) -> Self {
+        self.stdout_bytes = (min, max);
+        self
+    }
+
+    // expected to fail, reported as XFail on failure and XPass on success
+    pub fn xfail(mut self, xfail: bool) -> Self {
+        self.xfail = xfail;
+        self
+    }
+
+    // rejects a program that is not an ELF executable for this machine
+    // before running it, native runner only
+    pub fn require_elf(mut self, require_elf: bool) -> Self {
+        self.require_elf = require_elf;
+        self
+    }
+
+    // allows a blank args template, nothing is run then and the agent only
+    // inspects what is on disk
+    pub fn artifact_only(mut self, artifact_only: bool) -> Self {
+        self.artifact_only = artifact_only;
+        self
+    }
+
     pub fn communicate(mut self, require_communicator: bool) -> Self {
         self.communicate = require_communicator;
         self
@@ -133,26 +953,197 @@ impl TestTemplateBuilder {
         self
     }
 
+    // number of concurrent communicators run against the process
+    pub fn clients(mut self, clients: usize) -> Self {
+        self.clients = clients.max(1);
+        self
+    }
+
     pub fn build(self) -> TestTemplate {
         if self.test_factory.is_none() {
             panic!("[-] Test factory is required");
         }
 
+        // an empty command would be validated without running anything
+        if self.cmd_args_template.trim().is_empty() && !self.artifact_only {
+            panic!(
+                "[-] Args template is required unless the test is artifact only: {}",
+                self.name
+            );
+        }
+
+        let mut stages = self.pipe_from.iter().chain(&self.pipe_into);
+        if stages.any(|stage| stage.trim().is_empty()) {
+            panic!("[-] A pipeline stage cannot be empty: {}", self.name);
+        }
+
+        // the stages run with plain pipes, these need the program spawned
+        // on its own
+        let pipeline = !self.pipe_from.is_empty() || !self.pipe_into.is_empty();
+        let unsupported = [
+            ("a communicator", self.communicate),
+            ("a stdin script", self.stdin_script.is_some()),
+            ("an input grace", self.input_grace.is_some()),
+            ("a timeout signal", self.timeout_signal.is_some()),
+            ("a process group", self.process_group),
+            ("a pty", self.pty),
+            (
+                "discarded output",
+                self.discard_stdout || self.discard_stderr,
+            ),
+            ("an output tail", self.output_tail.is_some()),
+            (
+                "output sentinels",
+                self.fail_on_stdout.is_some()
+                    || self.done_on_stdout.is_some()
+                    || self.fail_on_stderr.is_some(),
+            ),
+            ("max_open_fds", self.max_open_fds.is_some()),
+            ("max_rss", self.max_rss.is_some()),
+            ("socket leak detection", self.detect_socket_leaks),
+        ];
+        if let Some((option, _)) =
+            unsupported.iter().find(|(_, used)| pipeline && *used)
+        {
+            panic!("[-] A pipeline test cannot use {}: {}", option, self.name);
+        }
+
         TestTemplate {
             name: self.name,
+            suite: self.suite,
+            description: self.description,
             cmd_args_template: self.cmd_args_template,
+            literal_braces: self.literal_braces,
+            pipe_from: self.pipe_from,
+            pipe_into: self.pipe_into,
+            runner: self.runner,
+            preserved_paths: self.preserved_paths,
             test_factory: self.test_factory.unwrap(),
             log_output: self.log_output,
+            output_tail: self.output_tail,
+            report_output_lines: self.report_output_lines,
+            fail_on_stdout: self.fail_on_stdout.clone(),
+            done_on_stdout: self.done_on_stdout.clone(),
+            fail_on_stderr: self.fail_on_stderr.clone(),
+            max_open_fds: self.max_open_fds,
+            detect_socket_leaks: self.detect_socket_leaks,
+            max_rss: self.max_rss,
+            max_allocs: self.max_allocs,
+            stdout_bytes: self.stdout_bytes,
+            xfail: self.xfail,
+            points: self.points,
+            isolated: self.isolated,
+            smoke: self.smoke,
+            dependencies: self.dependencies.clone(),
+            require_elf: self.require_elf,
             valgrind: self.valgrind,
+            valgrind_args: self.valgrind_args,
+            valgrind_suppressions: self.valgrind_suppressions,
+            valgrind_verbose: self.valgrind_verbose,
+            detect_asan: self.detect_asan,
             require_communicator: self.communicate,
             timeout: self.timeout,
+            soft_timeout: self.soft_timeout,
+            timeout_signal: self.timeout_signal,
+            nice: self.nice,
+            env: self.env,
+            input_grace: self.input_grace,
+            stdin_script: self.stdin_script.clone(),
+            process_group: self.process_group,
+            strict_env: self.strict_env,
+            pty: self.pty,
+            discard_stdout: self.discard_stdout,
+            discard_stderr: self.discard_stderr,
+            read_buffer: self.read_buffer,
+            expected_termination: self.expected_termination,
+            normalizer: self.normalizer,
+            prompt: self.prompt,
+            expected_stdout: self.expected_stdout,
+            expected_stderr: self.expected_stderr,
+            expected_stderr_file: self.expected_stderr_file,
+            ignore_line_order: self.ignore_line_order,
+            verbose_diff: self.verbose_diff,
             operation_timeout: self.operation_timeout,
+            clients: self.clients,
         }
     }
 }
 
 impl TestTemplate {
     pub fn instantiate(&self, port: Option<u16>) -> Test {
+        self.instantiate_in(&self.name, port, &HashMap::new(), None)
+    }
+
+    // `{name}` placeholders are replaced from `substitutions`, `{}` is still
+    // the port; panics on a placeholder left unresolved
+    pub fn instantiate_with(
+        &self,
+        port: Option<u16>,
+        substitutions: &HashMap<String, String>,
+    ) -> Test {
+        self.instantiate_in(&self.name, port, substitutions, None)
+    }
+
+    // the args or a pipeline stage take the port through `{}`
+    fn templates_port(&self) -> bool {
+        let mut stages = self.pipe_from.iter().chain(&self.pipe_into);
+        self.cmd_args_template.contains("{}")
+            || stages.any(|stage| stage.contains("{}"))
+    }
+
+    // the port, substitutions and environment variables filled in, split
+    // into arguments
+    fn expand_args(
+        &self,
+        template: &str,
+        port: u16,
+        substitutions: &HashMap<String, String>,
+    ) -> Vec<String> {
+        // before the placeholders, `${NAME}` would read as one
+        let lookup = |name: &str| {
+            self.env
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .or_else(|| std::env::var(name).ok())
+        };
+        let template = expand_env_vars(template, lookup, self.strict_env)
+            .unwrap_or_else(|name| {
+                panic!(
+                    "[-] Unresolved environment variable ${} in test: {}",
+                    name, self.name
+                )
+            });
+
+        let cmd_args = if template.contains("{}") {
+            template.replace("{}", &port.to_string())
+        } else {
+            template
+        };
+
+        let lookup = |name: &str| substitutions.get(name).cloned();
+        let literal = &self.literal_braces;
+        let cmd_args = expand_placeholders(&cmd_args, lookup, literal)
+            .unwrap_or_else(|name| {
+                panic!(
+                    "[-] Unresolved placeholder {{{}}} in test: {}",
+                    name, self.name
+                )
+            });
+
+        cmd_args.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    // `name` is the test's, which the logs are named after; logs go to
+    // `results_dir` when given, otherwise to the tests directory
+    fn instantiate_in(
+        &self,
+        name: &str,
+        port: Option<u16>,
+        substitutions: &HashMap<String, String>,
+        results_dir: Option<&Path>,
+    ) -> Test {
         // ----- sanity checks -----
         if self.require_communicator && port.is_none() {
             panic!("[-] Port number is required for test: {}", self.name);
@@ -165,56 +1156,173 @@ impl TestTemplate {
             );
         }
 
-        if self.cmd_args_template.contains("{}") && port.is_none() {
+        if self.templates_port() && port.is_none() {
             panic!("[-] Port number is required for test: {}", self.name);
         }
 
         let port = port.unwrap_or(0);
-        let cmd_args = if self.cmd_args_template.contains("{}") {
-            self.cmd_args_template.replace("{}", &port.to_string())
-        } else {
-            self.cmd_args_template.clone()
+        let mut cmd_args =
+            self.expand_args(&self.cmd_args_template, port, substitutions);
+        let pipe_from = self
+            .pipe_from
+            .iter()
+            .map(|stage| self.expand_args(stage, port, substitutions))
+            .collect();
+        let pipe_into = self
+            .pipe_into
+            .iter()
+            .map(|stage| self.expand_args(stage, port, substitutions))
+            .collect();
+
+        let mut valgrind_error_code = None;
+        let mut valgrind_log = None;
+        let mut script = None;
+        let binary = match (&self.runner, self.require_elf) {
+            (Runner::Native, true) => cmd_args.first().cloned(),
+            _ => None,
         };
-
-        let mut cmd_args: Vec<String> =
-            cmd_args.split_whitespace().map(|s| s.to_string()).collect();
+        if let Runner::Interpreter(interpreter) = &self.runner {
+            script = cmd_args.first().cloned();
+            cmd_args = interpreter
+                .split_whitespace()
+                .map(|s| s.to_string())
+                .chain(cmd_args)
+                .collect();
+        }
 
         if self.valgrind {
             // construct valgrind arguments
-            let valgrind = vec![
-                String::from("valgrind"),
-                String::from("--leak-check=full"),
-                String::from("--tool=memcheck"),
-                String::from("--show-leak-kinds=all"),
-                String::from("--track-origins=yes"),
-                String::from("--verbose"),
-                String::from("--error-exitcode=1"),
-                String::from("-v"),
-                format!("--log-file=valgrind - {}", self.name),
-            ];
+            let mut valgrind = vec![String::from("valgrind")];
+            match &self.valgrind_args {
+                Some(args) => valgrind.extend(args.iter().cloned()),
+                None => valgrind.extend(default_valgrind_args()),
+            }
+
+            if self.valgrind_verbose {
+                valgrind.push(String::from("--verbose"));
+                valgrind.push(String::from("-v"));
+            }
+
+            // valgrind reports the descriptors still open at exit
+            if self.max_open_fds.is_some() {
+                valgrind.push(String::from("--track-fds=yes"));
+            }
+
+            if let Some(suppressions) = &self.valgrind_suppressions {
+                valgrind
+                    .push(format!("--suppressions={}", suppressions.display()));
+            }
+
+            let log_file = format!("valgrind - {}", name);
+            let log_file = match results_dir {
+                Some(dir) => dir.join(log_file).display().to_string(),
+                None => log_file,
+            };
+            valgrind.push(format!("--log-file={}", log_file));
+            valgrind_log = Some(PathBuf::from(log_file));
+
+            // the status valgrind exits with when it detects errors
+            valgrind_error_code = valgrind
+                .iter()
+                .rev()
+                .find_map(|arg| arg.strip_prefix("--error-exitcode="))
+                .and_then(|code| code.parse().ok());
 
             // chain valgrind arguments with the command arguments
             cmd_args = valgrind.into_iter().chain(cmd_args).collect();
         }
 
         Test {
-            name: self.name.clone(),
+            name: name.to_string(),
+            suite: self.suite.clone(),
+            description: self.description.clone(),
             cmd_args,
+            pipe_from,
+            pipe_into,
+            script,
+            binary,
+            results_dir: results_dir.map(Path::to_path_buf),
+            valgrind_error_code,
+            valgrind_log,
+            detect_asan: self.detect_asan,
+            preserved_paths: self.preserved_paths.clone(),
             test: (self.test_factory)(),
-            timeout: self.timeout,
+            timeout: self.timeout.unwrap_or(0),
+            soft_timeout: self.soft_timeout,
+            timeout_signal: self.timeout_signal,
+            nice: self.nice,
+            env: self.env.clone(),
+            input_grace: self.input_grace,
+            stdin_script: self.stdin_script.clone(),
+            process_group: self.process_group,
+            pty: self.pty,
+            discard_stdout: self.discard_stdout,
+            discard_stderr: self.discard_stderr,
+            read_buffer: self.read_buffer,
+            stdin: None,
+            expected_stdout: self.expected_stdout.clone(),
+            expected_stderr: self.expected_stderr.clone(),
+            expected_stderr_file: self.expected_stderr_file.clone(),
+            ignore_line_order: self.ignore_line_order,
+            verbose_diff: self.verbose_diff,
+            context: TestContext::default(),
+            expected_termination: self.expected_termination.clone(),
+            normalizer: self.normalizer.clone(),
+            prompt: self.prompt.clone(),
             log_output: self.log_output,
+            output_tail: self.output_tail,
+            report_output_lines: self.report_output_lines,
+            fail_on_stdout: self.fail_on_stdout.clone(),
+            done_on_stdout: self.done_on_stdout.clone(),
+            fail_on_stderr: self.fail_on_stderr.clone(),
+            max_open_fds: self.max_open_fds,
+            detect_socket_leaks: self.detect_socket_leaks,
+            max_rss: self.max_rss,
+            max_allocs: self.max_allocs,
+            stdout_bytes: self.stdout_bytes,
+            xfail: self.xfail,
+            points: self.points,
+            isolated: self.isolated,
+            smoke: self.smoke,
+            dependencies: self.dependencies.clone(),
             require_communicator: self.require_communicator,
             operation_timeout: self.operation_timeout,
+            clients: self.clients,
             port,
+            recorded: std::sync::Mutex::new(None),
+            log_budget: None,
         }
     }
 }
 
+impl Test {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cmd_args(&self) -> &[String] {
+        &self.cmd_args
+    }
+
+    // none when instantiated without a port
+    pub fn port(&self) -> Option<u16> {
+        (self.port != 0).then_some(self.port)
+    }
+
+    // in seconds, 0 for no timeout
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    pub fn requires_communicator(&self) -> bool {
+        self.require_communicator
+    }
+}
+
 impl Test {
     fn on_validate(&self, test_output: &ProcessOutput) -> bool {
         // panic if exercise failed to run due port already in use
-        let stderr =
-            String::from_utf8_lossy(&test_output.stderr).to_lowercase();
+        let stderr = test_output.stderr_str().to_lowercase();
         if stderr.contains("in use")
         // address already in use
         {
@@ -226,6 +1334,46 @@ impl Test {
             return false;
         }
 
+        if let Some(sentinel) = &test_output.sentinel {
+            println!("[-] Test aborted, its output contained {:?}", sentinel);
+            return false;
+        }
+
+        if test_output.blocked_on_input {
+            println!("[-] Test blocked on input, it kept running after its stdin ended");
+            return false;
+        }
+
+        // under valgrind this exit code means valgrind found memory errors,
+        // it must not be mistaken for the program's own exit code
+        if let (Ok(status), Some(error_code)) =
+            (&test_output.status, self.valgrind_error_code)
+        {
+            if status.code() == Some(error_code) {
+                println!("[-] Valgrind detected memory errors");
+                return false;
+            }
+        }
+
+        if self.detect_asan {
+            if let Some(kind) = detect_asan_error(&test_output.stderr) {
+                println!("[-] AddressSanitizer detected {}", kind);
+                return false;
+            }
+        }
+
+        if let (Ok(status), Some(expected)) =
+            (&test_output.status, &self.expected_termination)
+        {
+            return self.check_termination(status, expected);
+        }
+
+        // killed once it was done, not crashed
+        if let Some(done) = &test_output.done_sentinel {
+            println!("[*] Test stopped once its output contained {:?}", done);
+            return true;
+        }
+
         match &test_output.status {
             Ok(ref status) => match status.code() {
                 Some(code) => {
@@ -233,7 +1381,8 @@ impl Test {
                         return true;
                     }
 
-                    if code == Status::Timeout as i32 {
+                    // Status values are raw wait statuses, not exit codes
+                    if status.into_raw() == Status::Timeout as i32 {
                         println!("[-] Test timed out");
                         return false;
                     }
@@ -260,127 +1409,968 @@ impl Test {
             },
 
             Err(e) => {
-                panic!("[-] Failed to run test: {}", e);
+                println!("[-] Failed to run test: {}", e);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn check_expected_stdout(&self, stdout: &[u8]) -> bool {
+        let expected = match &self.expected_stdout {
+            Some(expected) => expected,
+            None => return true,
+        };
+
+        if self.ignore_line_order {
+            if let Err(e) = compare_lines_unordered(stdout, expected) {
+                println!(
+                    "[-] Output does not match the expected output, {}",
+                    e
+                );
+                return false;
+            }
+        } else if stdout != expected.as_slice() {
+            if self.verbose_diff {
+                println!("[-] Output does not match the expected output:");
+                print!("{}", line_diff_visible(expected, stdout));
+            } else {
+                println!("[-] Output does not match the expected output");
+            }
+            return false;
+        }
+
+        true
+    }
+
+    fn check_expected_stderr(&self, cwd: &Path, stderr: &[u8]) -> bool {
+        let expected = if let Some(expected) = &self.expected_stderr {
+            expected.clone()
+        } else if let Some(path) = &self.expected_stderr_file {
+            let path = cwd.join(path);
+            match std::fs::read(&path) {
+                Ok(expected) => expected,
+                Err(e) => {
+                    println!(
+                        "[-] Failed to read expected stderr {:?}: {}",
+                        path, e
+                    );
+                    return false;
+                }
+            }
+        } else {
+            return true;
+        };
+
+        let stderr = match &self.normalizer {
+            Some(normalizer) => normalizer(stderr),
+            None => stderr.to_vec(),
+        };
+        if self.ignore_line_order {
+            if let Err(e) = compare_lines_unordered(&stderr, &expected) {
+                println!(
+                    "[-] Stderr does not match the expected stderr, {}",
+                    e
+                );
+                return false;
             }
+        } else if stderr != expected {
+            println!("[-] Stderr does not match the expected stderr:");
+            match self.verbose_diff {
+                true => print!("{}", line_diff_visible(&expected, &stderr)),
+                false => print!("{}", line_diff(&expected, &stderr)),
+            }
+            return false;
         }
+
         true
     }
+
+    fn check_peak_rss(&self, peak_rss_bytes: Option<u64>) -> bool {
+        match (self.max_rss, peak_rss_bytes) {
+            (Some(max_rss), Some(peak)) if peak > max_rss => {
+                println!(
+                    "[-] Test used {} bytes of memory at peak, at most {} allowed",
+                    peak, max_rss
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn check_sockets(&self, leaked: bool) -> bool {
+        if leaked {
+            println!("[-] Test left sockets open after it ended");
+        }
+        !leaked
+    }
+
+    fn check_stdout_bytes(&self, bytes: usize) -> bool {
+        match self.stdout_bytes {
+            (Some(min), _) if bytes < min => {
+                println!(
+                    "[-] Test printed {} bytes to stdout, at least {} expected",
+                    bytes, min
+                );
+                false
+            }
+            (_, Some(max)) if bytes > max => {
+                println!(
+                    "[-] Test printed {} bytes to stdout, at most {} allowed",
+                    bytes, max
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn check_allocs(&self, allocs: Option<u64>) -> bool {
+        match (self.max_allocs, allocs) {
+            (Some(max_allocs), Some(allocs)) if allocs > max_allocs => {
+                println!(
+                    "[-] Test made {} heap allocations, at most {} allowed",
+                    allocs, max_allocs
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn check_open_fds(&self, open_fds: usize) -> bool {
+        match self.max_open_fds {
+            Some(max_open_fds) if open_fds > max_open_fds => {
+                println!(
+                    "[-] Test held {} file descriptors open, at most {} allowed",
+                    open_fds, max_open_fds
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn check_termination(
+        &self,
+        status: &ExitStatus,
+        expected: &Termination,
+    ) -> bool {
+        if status.into_raw() == Status::Timeout as i32 {
+            println!("[-] Test timed out");
+            return false;
+        }
+
+        match (expected, status.code(), status.signal()) {
+            (Termination::Exit(codes), Some(code), _) => {
+                if codes.contains(&code) {
+                    return true;
+                }
+                println!(
+                    "[-] Test exited with status code: {}, expected: {:?}",
+                    code, codes
+                );
+            }
+            (Termination::Exit(codes), None, Some(signal)) => {
+                println!(
+                    "[-] Test was killed by signal: {}, expected exit code: {:?}",
+                    signal, codes
+                );
+            }
+            (Termination::Signal(expected), _, Some(signal)) => {
+                if signal == *expected {
+                    return true;
+                }
+                println!(
+                    "[-] Test was killed by signal: {}, expected signal: {}",
+                    signal, expected
+                );
+            }
+            (Termination::Signal(expected), code, None) => {
+                println!(
+                    "[-] Test exited with status code: {:?}, expected signal: {}",
+                    code, expected
+                );
+            }
+            (Termination::Exit(_), None, None) => {
+                println!("[-] Test terminated without an exit status");
+            }
+        }
+        false
+    }
 }
 
 impl Test {
+    fn run_blocking(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+    ) -> TestOutcome {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(self.run(cwd, startup_delay))
+    }
+
+    // a panicking test (missing binary, failed spawn...) is recorded as
+    // errored instead of tearing down the caller
+    fn run_isolated(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+    ) -> TestOutcome {
+        let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            self.run_blocking(cwd, startup_delay)
+        }));
+
+        outcome.unwrap_or_else(|_| {
+            println!("[-] {} test aborted in {:?}", self.name, cwd);
+            self.errored(format!("test aborted in {:?}", cwd))
+        })
+    }
+
+    // takes `len` bytes from the log budget, if there is one
+    fn log_allowed(&self, len: usize) -> bool {
+        self.log_budget
+            .as_ref()
+            .is_none_or(|budget| budget.take(len))
+    }
+
+    fn log_path(&self, cwd: &Path, file_name: &str) -> PathBuf {
+        self.results_dir.as_deref().unwrap_or(cwd).join(file_name)
+    }
+
+    // the run broke down before a verdict could be reached
+    fn errored(&self, reason: String) -> TestOutcome {
+        TestOutcome {
+            status: TestStatus::Errored,
+            reason: Some(reason),
+            ..self.outcome(false)
+        }
+    }
+
+    fn outcome(&self, passed: bool) -> TestOutcome {
+        TestOutcome {
+            suite: self.suite.clone(),
+            description: self.description.clone(),
+            status: match (self.xfail, passed) {
+                (false, true) => TestStatus::Passed,
+                (false, false) => TestStatus::Failed,
+                (true, true) => TestStatus::XPass,
+                (true, false) => TestStatus::XFail,
+            },
+            exit_code: None,
+            points: match (self.xfail, passed) {
+                (false, true) => self.points,
+                _ => 0.0,
+            },
+            duration: Duration::ZERO,
+            slow: false,
+            stdout_bytes: 0,
+            stdout_lines: 0,
+            stderr_bytes: 0,
+            stderr_lines: 0,
+            peak_rss_bytes: None,
+            allocs: None,
+            truncated_by_timeout: false,
+            socket_leak_detected: false,
+            stage_exit_codes: Vec::new(),
+            timings: PhaseTimings::default(),
+            metadata: HashMap::new(),
+            stdout_excerpt: None,
+            stderr_excerpt: None,
+            reason: None,
+        }
+    }
+
+    fn skipped(&self, reason: &str) -> TestOutcome {
+        TestOutcome {
+            status: TestStatus::Skipped,
+            reason: Some(reason.to_string()),
+            ..self.outcome(false)
+        }
+    }
+
     pub async fn run(
         &self,
         cwd: &std::path::PathBuf,
         startup_delay: u64,
-    ) -> bool {
+    ) -> TestOutcome {
+        self.run_with_args(cwd, startup_delay, &[]).await
+    }
+
+    // `extra_args` are appended to the resolved command line
+    //
+    // phase events are emitted in a span carrying the test name
+    #[tracing::instrument(name = "test", skip_all, fields(name = %self.name))]
+    pub async fn run_with_args(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        extra_args: &[String],
+    ) -> TestOutcome {
         println!("[*] Running {} test...", self.name);
+        if !self.description.is_empty() {
+            println!("[*] {}", self.description);
+        }
+
+        let mut outcome =
+            self.run_in_copy(cwd, startup_delay, extra_args).await;
+
+        if let Some(soft_timeout) = self.soft_timeout {
+            outcome.slow = outcome.duration > Duration::from_secs(soft_timeout);
+            if outcome.slow {
+                println!(
+                    "[!] {} took {:?}, over its soft timeout of {}s",
+                    self.name, outcome.duration, soft_timeout
+                );
+            }
+        }
+        outcome
+    }
+
+    async fn run_in_copy(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        extra_args: &[String],
+    ) -> TestOutcome {
+        if !self.isolated {
+            return self.run_preserving(cwd, startup_delay, extra_args).await;
+        }
+
+        let copy = unique_temp_dir("tests_lib-isolated");
+        if let Err(e) = copy_recursively(cwd, &copy) {
+            println!("[-] Failed to copy tests directory: {}", e);
+            println!();
+            let _ = std::fs::remove_dir_all(&copy);
+            return self.outcome(false);
+        }
+
+        let outcome =
+            self.run_preserving(&copy, startup_delay, extra_args).await;
+        if let Err(e) = std::fs::remove_dir_all(&copy) {
+            println!("[!] Failed to remove {:?}: {}", copy, e);
+        }
+        outcome
+    }
+
+    async fn run_preserving(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        extra_args: &[String],
+    ) -> TestOutcome {
+        if self.preserved_paths.is_empty() {
+            return self.execute_bounded(cwd, startup_delay, extra_args).await;
+        }
+
+        // keep the declared files untouched for the tests that follow
+        let snapshot = match DirSnapshot::take(cwd, &self.preserved_paths) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                println!("[-] Failed to snapshot tests directory: {}", e);
+                println!();
+                return self.outcome(false);
+            }
+        };
+
+        let outcome =
+            self.execute_bounded(cwd, startup_delay, extra_args).await;
+        if let Err(e) = snapshot.restore() {
+            println!("[!] Failed to restore tests directory: {}", e);
+        }
+        outcome
+    }
+
+    // a ceiling on the whole run so a process and an agent deadlocking each
+    // other cannot hang the suite; a process without a timeout (0) is still
+    // bounded by the operation timeout, and with neither it has none
+    fn deadline(&self, startup_delay: u64) -> Option<Duration> {
+        let longest = self.timeout.max(self.operation_timeout);
+        if longest == 0 {
+            return None;
+        }
+        Some(Duration::from_secs(longest + startup_delay) + RUN_DEADLINE_SLACK)
+    }
+
+    // dropping the cut short run kills the process, see AbortOnDrop
+    async fn execute_bounded(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        extra_args: &[String],
+    ) -> TestOutcome {
+        // timed here so copying and restoring the tests directory is not
+        // counted
+        let started = Instant::now();
+        let run = self.execute(cwd, startup_delay, extra_args);
+        let deadline = self.deadline(startup_delay);
+        let outcome = match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, run).await,
+            None => Ok(run.await),
+        };
+        let duration = started.elapsed();
+        self.test.finalize().await;
+
+        match outcome {
+            Ok(outcome) => TestOutcome { duration, ..outcome },
+            Err(_) => {
+                println!(
+                    "[-] Test exceeded its overall deadline of {:?}",
+                    deadline.unwrap_or_default()
+                );
+                println!();
+                TestOutcome {
+                    duration,
+                    ..self.errored(String::from("deadline exceeded"))
+                }
+            }
+        }
+    }
+
+    async fn execute(
+        &self,
+        cwd: &std::path::PathBuf,
+        startup_delay: u64,
+        extra_args: &[String],
+    ) -> TestOutcome {
+        let cmd_args: Vec<String> =
+            self.cmd_args.iter().chain(extra_args).cloned().collect();
 
         // if no args are empty so we only do a valgrind check
         // therefore, we don't need to run the test
-        if self.cmd_args.is_empty() {
+        if cmd_args.is_empty() {
             // dummy process output
             let dummy = ProcessOutput::new(
                 Vec::new(),
                 Vec::new(),
                 Ok(ExitStatus::from_raw(0)),
             );
-            return self.test.validate(&self.cmd_args, None, dummy, cwd).await;
+            let timings = PhaseTimings {
+                compile: self
+                    .context
+                    .compile_report
+                    .as_ref()
+                    .map(|r| r.duration),
+                ..PhaseTimings::default()
+            };
+            return self
+                .conclude(
+                    cwd,
+                    &cmd_args,
+                    true,
+                    Vec::new(),
+                    dummy,
+                    self.outcome(false),
+                    timings,
+                )
+                .await;
+        }
+
+        println!("[*] Input: {}", cmd_args.join(" "));
+
+        // interpreted tests have no executable of their own to check
+        if let Some(script) = &self.script {
+            if !cwd.join(script).exists() {
+                println!("[-] Cannot run exercise, {:?} is not found", script);
+                println!();
+                return self.outcome(false);
+            }
+        }
+
+        let output_log =
+            self.log_path(cwd, &format!("output - {}.txt", self.name));
+        let tee = match self.output_tail {
+            Some(tail) if self.log_output => {
+                let tee = OutputTee::create(&output_log, tail).await;
+                let tee = tee.unwrap_or_else(|_| {
+                    panic!("Could not create file: {:?}", output_log)
+                });
+                Some(match &self.log_budget {
+                    Some(budget) => tee.with_budget(Arc::clone(budget)),
+                    None => tee,
+                })
+            }
+            _ => None,
+        };
+        let streamed = tee.is_some();
+
+        if let Some(binary) = &self.binary {
+            let valid = resolve_executable(binary, cwd)
+                .is_some_and(|path| is_valid_elf(&path));
+            if !valid {
+                println!(
+                    "[-] Cannot run exercise, {:?} is not an ELF executable for this machine",
+                    binary
+                );
+                println!();
+                return self.outcome(false);
+            }
         }
 
-        println!("[*] Input: {}", self.cmd_args.join(" "));
+        if !self.pipe_from.is_empty() || !self.pipe_into.is_empty() {
+            return self.execute_pipeline(cwd, cmd_args, output_log).await;
+        }
 
         // run the exercise in a shell as a child process
-        let test_proc = Arc::new(Mutex::new(
-            TestSpawner::new(&self.cmd_args, cwd, startup_delay).await,
-        ));
+        let options = SpawnOptions {
+            stdin: self
+                .stdin
+                .clone()
+                .or_else(|| self.input_grace.map(|_| Vec::new())),
+            tee,
+            fail_on_stdout: self.fail_on_stdout.clone(),
+            done_on_stdout: self.done_on_stdout.clone(),
+            fail_on_stderr: self.fail_on_stderr.clone(),
+            nice: self.nice,
+            env: self.env.clone(),
+            input_grace: self.input_grace,
+            stdin_script: self.stdin_script.clone(),
+            process_group: self.process_group,
+            pty: self.pty,
+            discard_stdout: self.discard_stdout,
+            discard_stderr: self.discard_stderr,
+            read_buffer: self.read_buffer,
+        };
+        let mut timings = PhaseTimings {
+            compile: self.context.compile_report.as_ref().map(|r| r.duration),
+            ..PhaseTimings::default()
+        };
+        let spawn_started = Instant::now();
+        let spawned =
+            TestSpawner::with_options(&cmd_args, cwd, startup_delay, options)
+                .await;
+        timings.ready = spawn_started.elapsed();
+        let test_proc = match spawned {
+            Ok(test_proc) => {
+                timings.spawn = test_proc.spawn_duration();
+                Arc::new(Mutex::new(test_proc))
+            }
+            Err(e) => {
+                println!("[-] Cannot run exercise, {}", e);
+                println!();
+                return self.errored(e.to_string());
+            }
+        };
+
+        // taken before the wait task holds the lock for the process lifetime
+        let process_id = test_proc.lock().await.id();
+
+        // sample the descriptors while the process runs, under valgrind the
+        // process is valgrind itself so its log is used instead
+        let open_fds = Arc::new(AtomicUsize::new(0));
+        let fd_tracker = match (self.max_open_fds, process_id) {
+            (Some(_), Some(pid)) if self.valgrind_log.is_none() => {
+                Some(tokio::spawn(track_open_fds(pid, Arc::clone(&open_fds))))
+            }
+            _ => None,
+        };
+
+        let sockets = Arc::new(std::sync::Mutex::new(SocketSamples::default()));
+        let socket_tracker = match process_id {
+            Some(pid) if self.detect_socket_leaks => {
+                Some(tokio::spawn(track_sockets(pid, Arc::clone(&sockets))))
+            }
+            _ => None,
+        };
+
+        let peak_rss = Arc::new(AtomicU64::new(0));
+        let rss_tracker = process_id.map(|pid| {
+            tokio::spawn(track_peak_rss(pid, Arc::clone(&peak_rss)))
+        });
 
         let total_timeout = self.timeout;
-        let test_output = tokio::spawn({
+        let timeout_signal = self.timeout_signal;
+        let mut test_output = tokio::spawn({
             let test_proc = Arc::clone(&test_proc);
 
             async move {
                 let mut proc = test_proc.lock().await;
-                return proc.wait(total_timeout).await;
+                let output =
+                    proc.wait_with_signal(total_timeout, timeout_signal).await;
+                (output, spawn_started.elapsed())
             }
         });
+        let _wait_task = AbortOnDrop(test_output.abort_handle());
 
         // optionally communicate with the process
-        let communicate_output: Option<CommunicateOutput> =
-            match self.require_communicator {
-                true => {
-                    let output = self
-                        .test
-                        .communicate(
+        let mut finished = None;
+        let communicate_outputs: Vec<CommunicateOutput> = match self
+            .require_communicator
+        {
+            true => {
+                tracing::debug!(phase = "communicate-start");
+                let communicate_started = Instant::now();
+                let port = self.port.to_string();
+                let communicate =
+                    futures::future::join_all((0..self.clients).map(|_| {
+                        self.test.communicate(
                             self.operation_timeout,
-                            &self.port.to_string(),
-                            test_proc.lock().await.id(),
+                            &port,
+                            process_id,
                         )
-                        .await;
+                    }));
+                tokio::pin!(communicate);
+
+                // stop communicating shortly after the process exits
+                // rather than retrying a dead server until timing out
+                let outputs = tokio::select! {
+                    outputs = &mut communicate => outputs,
+                    result = &mut test_output => {
+                        finished = Some(result);
+                        tokio::time::timeout(COMMUNICATE_GRACE, communicate)
+                            .await
+                            .unwrap_or_else(|_| {
+                                (0..self.clients)
+                                    .map(|_| CommunicateOutput {
+                                        error: Some(std::io::Error::other(
+                                            "process exited before communication finished",
+                                        )),
+                                        ..Default::default()
+                                    })
+                                    .collect()
+                            })
+                    }
+                };
 
+                for (i, output) in outputs.iter().enumerate() {
                     let mut output_to_log = output.output.clone();
 
                     if let Some(ref e) = output.error {
                         output_to_log.push(e.to_string().into_bytes());
                     }
 
-                    let log_path =
-                        cwd.join(format!("communicate - {}.txt", self.name));
+                    let file_name = match self.clients {
+                        1 => format!("communicate - {}.txt", self.name),
+                        _ => format!("communicate - {} #{}.txt", self.name, i),
+                    };
+                    let log_path = self.log_path(cwd, &file_name);
+                    let output_to_log = output_to_log.concat();
+                    if !self.log_allowed(output_to_log.len()) {
+                        continue;
+                    }
 
-                    tokio::fs::write(&log_path, output_to_log.concat())
+                    tokio::fs::write(&log_path, output_to_log)
                         .await
-                        .expect(&format!(
-                            "Could not write to file: {:?}",
-                            log_path
-                        ));
-
-                    Some(output)
+                        .unwrap_or_else(|_| {
+                            panic!("Could not write to file: {:?}", log_path)
+                        });
                 }
-                false => None,
-            };
 
-        // wait for the process to finish
-        let test_output = test_output.await.expect("failed to join process");
+                tracing::debug!(phase = "communicate-end");
+                timings.communicate = communicate_started.elapsed();
+                outputs
+            }
+            false => Vec::new(),
+        };
 
-        // log stdout and stderr
-        if self.log_output {
-            let log_path = cwd.join(format!("output - {}.txt", self.name));
-            let mut log_file = tokio::fs::File::create(&log_path).await.expect(
-                format!("Could not create file: {:?}", log_path).as_str(),
-            );
-            // log stdout
-            log_file.write_all(&test_output.stdout).await.expect(
-                format!("Could not write to file: {:?}", log_path).as_str(),
-            );
-            // log stderr
-            log_file.write_all(&test_output.stderr).await.expect(
-                format!("Could not write to file: {:?}", log_path).as_str(),
-            );
+        // wait for the process to finish
+        let test_output = match finished {
+            Some(result) => result,
+            None => test_output.await,
+        };
+        let (test_output, process_duration) =
+            test_output.expect("failed to join process");
+        timings.process = process_duration;
+        tracing::debug!(phase = "process-exit", status = ?test_output.status);
+        let trackers = fd_tracker.into_iter().chain(rss_tracker);
+        for tracker in trackers.chain(socket_tracker) {
+            tracker.abort();
         }
 
-        let is_not_errored = self.on_validate(&test_output);
-        let is_confirmed = self
-            .test
-            .validate(&self.cmd_args, communicate_output, test_output, cwd)
-            .await;
+        self.keep_output(output_log, streamed, &test_output).await;
 
-        println!();
+        let mut outcome = self.outcome(false).with_output_metrics(&test_output);
+        if let Some(lines) = self.report_output_lines {
+            outcome.stdout_excerpt =
+                Some(excerpt_lines(&test_output.stdout, lines));
+            outcome.stderr_excerpt =
+                Some(excerpt_lines(&test_output.stderr, lines));
+        }
+        outcome.peak_rss_bytes = match peak_rss.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes),
+        };
+        let open_fds = match &self.valgrind_log {
+            Some(log) if self.max_open_fds.is_some() => {
+                std::fs::read_to_string(cwd.join(log))
+                    .ok()
+                    .and_then(|log| valgrind_open_fds(&log))
+                    .unwrap_or(0)
+            }
+            _ => open_fds.load(Ordering::Relaxed),
+        };
+        outcome.socket_leak_detected = self.detect_socket_leaks && {
+            let sockets = sockets.lock().unwrap();
+            (test_output.truncated_by_timeout && !sockets.last.is_empty())
+                || !sockets.seen.is_disjoint(&open_tcp_inodes())
+        };
+        outcome.allocs = self
+            .valgrind_log
+            .as_ref()
+            .and_then(|log| std::fs::read_to_string(cwd.join(log)).ok())
+            .and_then(|log| ValgrindSummary::parse(&log))
+            .map(|summary| summary.allocs);
+        // the process could not be waited for, there is nothing to judge
+        if let Err(e) = &test_output.status {
+            println!("[-] Failed to run test: {}", e);
+            println!();
+            return TestOutcome {
+                timings,
+                ..self.errored(e.to_string())
+            };
+        }
 
-        is_not_errored && is_confirmed
-    }
-}
+        let is_not_errored = self.on_validate(&test_output)
+            && self.check_open_fds(open_fds)
+            && self.check_peak_rss(outcome.peak_rss_bytes)
+            && self.check_allocs(outcome.allocs)
+            && self.check_sockets(outcome.socket_leak_detected)
+            && self.check_stdout_bytes(outcome.stdout_bytes)
+            && self.check_expected_stderr(cwd, &test_output.stderr);
 
-impl<'a> TestManager<'a> {
-    pub fn new(name: &'a str, tests_dirname: &str, startup_delay: u64) -> Self {
-        let tests_dir_path =
-            std::env::current_dir().unwrap().join(tests_dirname);
-        if !tests_dir_path.exists() {
-            panic!("[-] Tests directory not found: {:?}", tests_dir_path);
-        }
+        self.conclude(
+            cwd,
+            &cmd_args,
+            is_not_errored,
+            communicate_outputs,
+            test_output,
+            outcome,
+            timings,
+        )
+        .await
+    }
 
-        Self {
+    // the program runs as one stage, the last stage's output is validated
+    async fn execute_pipeline(
+        &self,
+        cwd: &std::path::PathBuf,
+        cmd_args: Vec<String>,
+        output_log: PathBuf,
+    ) -> TestOutcome {
+        let stages: Vec<Vec<String>> = self
+            .pipe_from
+            .iter()
+            .cloned()
+            .chain(std::iter::once(cmd_args.clone()))
+            .chain(self.pipe_into.iter().cloned())
+            .collect();
+        let pipeline: Vec<String> =
+            stages.iter().map(|stage| stage.join(" ")).collect();
+        println!("[*] Pipeline: {}", pipeline.join(" | "));
+
+        let mut timings = PhaseTimings {
+            compile: self.context.compile_report.as_ref().map(|r| r.duration),
+            ..PhaseTimings::default()
+        };
+        let options = PipelineOptions {
+            stdin: self.stdin.clone(),
+            program: self.pipe_from.len(),
+            env: self.env.clone(),
+            nice: self.nice,
+        };
+        let PipelineOutput { output, statuses, duration } =
+            run_pipeline(&stages, cwd, self.timeout, &options).await;
+        timings.process = duration;
+
+        for (stage, status) in pipeline.iter().zip(&statuses) {
+            match status {
+                Ok(status) => {
+                    println!("[*] Stage {} exited with {}", stage, status)
+                }
+                Err(e) => println!("[-] Stage {} could not run, {}", stage, e),
+            }
+        }
+
+        let stage_exit_codes = statuses
+            .iter()
+            .map(|status| status.as_ref().ok().and_then(|s| s.code()))
+            .collect();
+        let spawn_errors: Vec<String> = pipeline
+            .iter()
+            .zip(&statuses)
+            .filter_map(|(stage, status)| {
+                let e = status.as_ref().err()?;
+                Some(format!("{} could not run, {}", stage, e))
+            })
+            .collect();
+        if !spawn_errors.is_empty() {
+            println!();
+            return TestOutcome {
+                stage_exit_codes,
+                timings,
+                ..self.errored(spawn_errors.join("; "))
+            };
+        }
+
+        self.keep_output(output_log, false, &output).await;
+
+        let mut outcome = self.outcome(false).with_output_metrics(&output);
+        outcome.stage_exit_codes = stage_exit_codes;
+        if let Some(lines) = self.report_output_lines {
+            outcome.stdout_excerpt = Some(excerpt_lines(&output.stdout, lines));
+            outcome.stderr_excerpt = Some(excerpt_lines(&output.stderr, lines));
+        }
+        outcome.allocs = self
+            .valgrind_log
+            .as_ref()
+            .and_then(|log| std::fs::read_to_string(cwd.join(log)).ok())
+            .and_then(|log| ValgrindSummary::parse(&log))
+            .map(|summary| summary.allocs);
+
+        // a stage exiting with an error is left for the agent to judge
+        let is_not_errored = self.on_validate(&output)
+            && self.check_allocs(outcome.allocs)
+            && self.check_stdout_bytes(outcome.stdout_bytes)
+            && self.check_expected_stderr(cwd, &output.stderr);
+
+        self.conclude(
+            cwd,
+            &cmd_args,
+            is_not_errored,
+            Vec::new(),
+            output,
+            outcome,
+            timings,
+        )
+        .await
+    }
+
+    // logs stdout and stderr, unless already streamed to the log
+    async fn keep_output(
+        &self,
+        output_log: PathBuf,
+        streamed: bool,
+        test_output: &ProcessOutput,
+    ) {
+        let output_len = test_output.stdout.len() + test_output.stderr.len();
+        if self.log_output && !streamed && self.log_allowed(output_len) {
+            let log_path = output_log;
+            let mut log_file = tokio::fs::File::create(&log_path)
+                .await
+                .unwrap_or_else(|_| {
+                    panic!("Could not create file: {:?}", log_path)
+                });
+            // log stdout
+            log_file
+                .write_all(&test_output.stdout)
+                .await
+                .unwrap_or_else(|_| {
+                    panic!("Could not write to file: {:?}", log_path)
+                });
+            // log stderr
+            log_file
+                .write_all(&test_output.stderr)
+                .await
+                .unwrap_or_else(|_| {
+                    panic!("Could not write to file: {:?}", log_path)
+                });
+        }
+
+        if let Some(recorded) = self.recorded.lock().unwrap().as_mut() {
+            *recorded =
+                (test_output.stdout.clone(), test_output.stderr.clone());
+        }
+    }
+
+    // the output checks and the agent's verdict, once the run is over
+    #[allow(clippy::too_many_arguments)]
+    async fn conclude(
+        &self,
+        cwd: &std::path::PathBuf,
+        cmd_args: &Vec<String>,
+        is_not_errored: bool,
+        mut communicate_outputs: Vec<CommunicateOutput>,
+        mut test_output: ProcessOutput,
+        outcome: TestOutcome,
+        mut timings: PhaseTimings,
+    ) -> TestOutcome {
+        if let Some(prompt) = &self.prompt {
+            test_output.stdout = strip_prompt(&test_output.stdout, prompt);
+        }
+        if let Some(normalizer) = &self.normalizer {
+            test_output.stdout = normalizer(&test_output.stdout);
+        }
+
+        let is_not_errored =
+            is_not_errored && self.check_expected_stdout(&test_output.stdout);
+
+        tracing::debug!(phase = "validate-start");
+        let validate_started = Instant::now();
+        // left over from a run that overran its deadline
+        self.context.take_metadata();
+        let is_confirmed = if communicate_outputs.len() > 1 {
+            self.test
+                .validate_clients_with_context(
+                    &self.context,
+                    cmd_args,
+                    communicate_outputs,
+                    test_output,
+                    cwd,
+                )
+                .await
+        } else {
+            let communicate_output = communicate_outputs.pop();
+            self.test
+                .validate_with_context(
+                    &self.context,
+                    cmd_args,
+                    communicate_output,
+                    test_output,
+                    cwd,
+                )
+                .await
+        };
+
+        tracing::debug!(phase = "validate-end", confirmed = is_confirmed);
+        timings.validate = validate_started.elapsed();
+
+        let verdict = self.outcome(is_not_errored && is_confirmed);
+        if verdict.status == TestStatus::XPass {
+            println!("[!] {} was expected to fail but passed", self.name);
+        }
+
+        println!();
+
+        let reason = match verdict.status {
+            TestStatus::Failed | TestStatus::XFail => {
+                self.test.failure_reason()
+            }
+            _ => None,
+        };
+        TestOutcome {
+            status: verdict.status,
+            points: verdict.points,
+            reason,
+            timings,
+            metadata: self.context.take_metadata(),
+            ..outcome
+        }
+    }
+}
+
+impl<'a> TestManager<'a> {
+    pub fn new(name: &'a str, tests_dirname: &str, startup_delay: u64) -> Self {
+        let tests_dir_path =
+            std::env::current_dir().unwrap().join(tests_dirname);
+        if !tests_dir_path.exists() {
+            panic!("[-] Tests directory not found: {:?}", tests_dir_path);
+        }
+
+        Self {
             name,
             tests_dir_path,
             startup_delay,
+            default_timeout: 0,
+            results_dir: None,
+            compile_patterns: CompilePatterns::default(),
+            compile_timeout: DEFAULT_COMPILE_TIMEOUT,
+            compile_command: None,
+            batch_concurrency: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            compile_report: None,
+            log_budget: None,
             templates: IndexMap::new(),
             active_tests: IndexMap::new(),
         }
@@ -388,18 +2378,260 @@ impl<'a> TestManager<'a> {
 }
 
 impl<'a> TestManager<'a> {
-    pub fn register_template(&mut self, template: TestTemplate) -> String {
-        self.templates.insert(template.name.clone(), template);
-        self.templates.last().unwrap().0.clone()
+    // applied to templates that don't set a timeout, 0 means no timeout
+    pub fn with_default_timeout(mut self, default_timeout: u64) -> Self {
+        self.default_timeout = default_timeout;
+        self
+    }
+
+    // logs of tests instantiated afterwards and of compile_assignment go
+    // here instead of the tests directory
+    pub fn with_results_dir(mut self, results_dir: &Path) -> Self {
+        // absolute, since valgrind resolves its log file from the tests dir
+        let results_dir = std::env::current_dir().unwrap().join(results_dir);
+        std::fs::create_dir_all(&results_dir).unwrap_or_else(|e| {
+            panic!("[-] Failed to create results directory: {}", e)
+        });
+        self.results_dir = Some(results_dir);
+        self
+    }
+
+    pub fn with_compile_patterns(
+        mut self,
+        compile_patterns: CompilePatterns,
+    ) -> Self {
+        self.compile_patterns = compile_patterns;
+        self
+    }
+
+    // kills a compile command still running after `timeout`, including
+    // those of run_batch; the compilation then counts as failed. Defaults
+    // to DEFAULT_COMPILE_TIMEOUT
+    pub fn with_compile_timeout(mut self, timeout: Duration) -> Self {
+        self.compile_timeout = timeout;
+        self
+    }
+
+    // the command run_batch compiles each submission with, without one the
+    // submissions are run as they are
+    pub fn with_compile_command(mut self, cmd: &str) -> Self {
+        self.compile_command = Some(cmd.to_string());
+        self
+    }
+
+    // tests needing a port still run one submission at a time, they all
+    // share the port of their active test
+    pub fn with_batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency.max(1);
+        self
+    }
+
+    // caps the bytes the output and communicate logs of all the tests
+    // instantiated afterwards may take together; past it the logs are no
+    // longer written, the tests still run
+    pub fn with_log_budget(mut self, bytes: u64) -> Self {
+        self.log_budget = Some(Arc::new(LogBudget::new(bytes)));
+        self
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // a taken name gets the first free suffix, a second "echo" is
+    // registered (and its tests named) as "echo#2", a third as "echo#3";
+    // returns the name the template was registered as
+    pub fn register_template(&mut self, mut template: TestTemplate) -> String {
+        let mut name = template.name.clone();
+        let mut n = 1;
+        while self.templates.contains_key(&name) {
+            n += 1;
+            name = format!("{}#{}", template.name, n);
+        }
+        if n > 1 {
+            println!(
+                "[!] Template {} is already registered, registering it as {}",
+                template.name, name
+            );
+        }
+
+        template.name = name.clone();
+        self.templates.insert(name.clone(), template);
+        name
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // registered templates in registration order, nothing is instantiated
+    pub fn catalog(&self) -> Vec<TemplateInfo> {
+        self.templates
+            .values()
+            .map(|template| {
+                let flags = [
+                    ("valgrind", template.valgrind),
+                    ("communicate", template.require_communicator),
+                    ("xfail", template.xfail),
+                    ("smoke", template.smoke),
+                ];
+                TemplateInfo {
+                    name: template.name.clone(),
+                    suite: template.suite.clone(),
+                    description: template.description.clone(),
+                    timeout: template.timeout.unwrap_or(self.default_timeout),
+                    soft_timeout: template.soft_timeout,
+                    points: template.points,
+                    tags: flags
+                        .iter()
+                        .filter(|(_, set)| *set)
+                        .map(|(tag, _)| tag.to_string())
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    // templates that communicate or put the port in their args, in
+    // registration order
+    pub fn templates_needing_port(&self) -> Vec<&str> {
+        self.templates
+            .values()
+            .filter(|template| {
+                template.require_communicator || template.templates_port()
+            })
+            .map(|template| template.name.as_str())
+            .collect()
+    }
+
+    // one test per registered template in registration order, asking
+    // `port_allocator` for the port of each template needing one; returns
+    // the names of the instantiated tests
+    pub fn instantiate_all<F>(&mut self, mut port_allocator: F) -> Vec<String>
+    where
+        F: FnMut(&str) -> u16,
+    {
+        let needing_port: Vec<String> = self
+            .templates_needing_port()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let template_names: Vec<String> =
+            self.templates.keys().cloned().collect();
+
+        let mut names = Vec::new();
+        for template_name in template_names {
+            let port = needing_port
+                .contains(&template_name)
+                .then(|| port_allocator(&template_name));
+            let test = self.new_test(
+                &template_name,
+                &template_name,
+                port,
+                &HashMap::new(),
+            );
+            names.push(test.name.clone());
+            self.active_tests.insert(test.name.clone(), test);
+        }
+        names
     }
 }
 
 impl<'a> TestManager<'a> {
     pub fn instantiate_test(&mut self, template_name: &str, port: Option<u16>) {
-        let template = self.templates.get(template_name).unwrap();
-        let test = template.instantiate(port);
+        self.instantiate_test_with(template_name, port, &HashMap::new());
+    }
+
+    pub fn instantiate_test_with(
+        &mut self,
+        template_name: &str,
+        port: Option<u16>,
+        substitutions: &HashMap<String, String>,
+    ) {
+        let test =
+            self.new_test(template_name, template_name, port, substitutions);
         self.active_tests.insert(test.name.clone(), test);
     }
+
+    fn new_test(
+        &self,
+        template_name: &str,
+        test_name: &str,
+        port: Option<u16>,
+        substitutions: &HashMap<String, String>,
+    ) -> Test {
+        let template = self.templates.get(template_name).unwrap();
+        // the copy an isolated test runs in is removed with its logs
+        let results_dir = match (&self.results_dir, template.isolated) {
+            (None, true) => Some(
+                std::fs::canonicalize(&self.tests_dir_path)
+                    .unwrap_or_else(|_| self.tests_dir_path.clone()),
+            ),
+            (results_dir, _) => results_dir.clone(),
+        };
+        let mut test = template.instantiate_in(
+            test_name,
+            port,
+            substitutions,
+            results_dir.as_deref(),
+        );
+        if template.timeout.is_none() {
+            test.timeout = self.default_timeout;
+        }
+        test.context.compile_report = self.compile_report.clone();
+        test.log_budget = self.log_budget.clone();
+        test
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // one test per `<stem>.in`/`<stem>.out` pair, named after the stem, fed
+    // the `.in` file on stdin and expected to print the `.out` file and exit
+    // with the code in `<stem>.exit`, or 0 without one unless the template
+    // sets its own expected termination
+    pub fn instantiate_from_fixtures(
+        &mut self,
+        template_name: &str,
+        fixtures_dir: &Path,
+    ) -> Result<Vec<String>, Error> {
+        let mut inputs: Vec<PathBuf> = std::fs::read_dir(fixtures_dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+            .collect();
+        inputs.sort();
+
+        let mut names = Vec::new();
+        for input in inputs {
+            let expected = input.with_extension("out");
+            if !expected.exists() {
+                println!("[!] Skipping fixture without output: {:?}", input);
+                continue;
+            }
+
+            let name = input.file_stem().unwrap().to_string_lossy();
+            let mut test =
+                self.new_test(template_name, &name, None, &HashMap::new());
+            test.stdin = Some(std::fs::read(&input)?);
+            test.expected_stdout = Some(std::fs::read(&expected)?);
+
+            let exit = input.with_extension("exit");
+            if exit.exists() {
+                let code = std::fs::read_to_string(&exit)?;
+                let code = code.trim().parse::<i32>().map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid exit code in {:?}: {}", exit, e),
+                    )
+                })?;
+                test.expected_termination =
+                    Some(Termination::Exit(code..=code));
+            } else if test.expected_termination.is_none() {
+                test.expected_termination = Some(Termination::Exit(0..=0));
+            }
+
+            names.push(test.name.clone());
+            self.active_tests.insert(test.name.clone(), test);
+        }
+
+        Ok(names)
+    }
 }
 
 impl<'a> TestManager<'a> {
@@ -416,34 +2648,3101 @@ impl<'a> TestManager<'a> {
 }
 
 impl<'a> TestManager<'a> {
-    pub fn compile_assignment(&self, cmd: &str) -> String {
+    // runs each command through sh in the tests directory, stopping at the
+    // first failing one, reported as Error::PreCompile; all output goes to
+    // pre_compile_output.txt
+    pub fn run_pre_compile(&self, commands: &[&str]) -> Result<(), Error> {
+        let log_dir = self.results_dir.as_ref().unwrap_or(&self.tests_dir_path);
+        let log_path = log_dir.join("pre_compile_output.txt");
+        let mut log = std::fs::File::create(&log_path)?;
+
+        for cmd in commands {
+            println!("[*] Running pre-compile step: {}", cmd);
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .current_dir(&self.tests_dir_path)
+                .output()
+                .map_err(|source| Error::Spawn {
+                    command: cmd.to_string(),
+                    source,
+                })?;
+
+            writeln!(log, "$ {}", cmd)?;
+            log.write_all(&output.stdout)?;
+            log.write_all(&output.stderr)?;
+
+            if !output.status.success() {
+                println!("[-] Pre-compile step failed: {}", cmd);
+                println!();
+                let mut combined = output.stdout;
+                combined.extend_from_slice(&output.stderr);
+                return Err(Error::PreCompile(CompileError {
+                    command: cmd.to_string(),
+                    output: String::from_utf8_lossy(&combined).into_owned(),
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    // the status as a string, "success", "warning", "error" or "timeout"
+    pub fn compile_assignment(&mut self, cmd: &str) -> String {
+        self.compile_assignment_result(cmd).as_str().to_string()
+    }
+
+    pub fn compile_assignment_result(&mut self, cmd: &str) -> CompileResult {
         println!("[*] Compiling assignment...");
-        let res = compile(cmd, &self.tests_dir_path);
+        let log_dir = self.results_dir.as_ref().unwrap_or(&self.tests_dir_path);
+        let log_path = log_dir.join("compilation_output.txt");
+        let started = Instant::now();
+        let res = compile_logged(
+            cmd,
+            &self.tests_dir_path,
+            &self.compile_patterns,
+            &log_path,
+            self.compile_timeout,
+        );
+        self.record_compile(cmd, res, &log_path, started.elapsed())
+    }
 
-        if res == "error" {
-            println!("[-] Compilation failed");
-        } else if res == "warning" {
-            println!("[!] Encountered warnings during compilation");
-        } else {
-            println!("[+] Compilation successful");
+    // like compile_assignment, without blocking the async runtime
+    pub async fn compile_assignment_async(&mut self, cmd: &str) -> String {
+        println!("[*] Compiling assignment...");
+        let log_dir = self.results_dir.as_ref().unwrap_or(&self.tests_dir_path);
+        let log_path = log_dir.join("compilation_output.txt");
+        let started = Instant::now();
+        let res = compile_logged_async(
+            cmd,
+            &self.tests_dir_path,
+            &self.compile_patterns,
+            &log_path,
+            self.compile_timeout,
+        )
+        .await;
+        let res = self.record_compile(cmd, res, &log_path, started.elapsed());
+        res.as_str().to_string()
+    }
+
+    fn record_compile(
+        &mut self,
+        cmd: &str,
+        res: CompileResult,
+        log_path: &Path,
+        duration: Duration,
+    ) -> CompileResult {
+        // shared with the tests so their agents can inspect it
+        let report = CompileReport {
+            command: cmd.to_string(),
+            status: res.as_str().to_string(),
+            output: std::fs::read(log_path)
+                .map(|log| String::from_utf8_lossy(&log).into_owned())
+                .unwrap_or_default(),
+            duration,
+        };
+        for test in self.active_tests.values_mut() {
+            test.context.compile_report = Some(report.clone());
+        }
+        self.compile_report = Some(report);
+
+        match res {
+            CompileResult::Timeout => println!(
+                "[-] Compilation timed out after {:?}",
+                self.compile_timeout
+            ),
+            CompileResult::Error => println!("[-] Compilation failed"),
+            CompileResult::Warning => {
+                println!("[!] Encountered warnings during compilation")
+            }
+            CompileResult::Success => println!("[+] Compilation successful"),
         }
 
         println!();
         res
     }
+
+    // compiles and, unless compilation fails, runs every active test
+    pub fn build_and_run(
+        &mut self,
+        compile_cmd: &str,
+    ) -> Result<Vec<(String, TestOutcome)>, Error> {
+        match self.compile_assignment_result(compile_cmd) {
+            CompileResult::Timeout => {
+                Err(Error::Timeout(self.compile_timeout))
+            }
+            CompileResult::Error => {
+                let report = self.compile_report.clone().unwrap();
+                Err(Error::Compile(CompileError {
+                    command: report.command,
+                    output: report.output,
+                }))
+            }
+            CompileResult::Success | CompileResult::Warning => {
+                Ok(self.run_tests())
+            }
+        }
+    }
+
+    // compiles like compile_assignment and, on failure, compiles again with
+    // DIAGNOSTIC_FLAGS into compilation_diagnostics.txt, whose content the
+    // error carries; the primary log and report are left as they were
+    pub fn compile_with_diagnostics(
+        &mut self,
+        cmd: &str,
+    ) -> Result<CompileResult, Error> {
+        match self.compile_assignment_result(cmd) {
+            CompileResult::Error => (),
+            // a build that never ends would only time out again
+            CompileResult::Timeout => {
+                return Err(Error::Timeout(self.compile_timeout));
+            }
+            res => return Ok(res),
+        }
+
+        let command = format!("{} {}", cmd, DIAGNOSTIC_FLAGS.join(" "));
+        println!("[*] Recompiling with diagnostics: {}", command);
+        let log_dir = self.results_dir.as_ref().unwrap_or(&self.tests_dir_path);
+        let log_path = log_dir.join("compilation_diagnostics.txt");
+        // a log left by an earlier run must not pass for this one's
+        let _ = std::fs::remove_file(&log_path);
+        let res = std::panic::catch_unwind(|| {
+            compile_logged(
+                &command,
+                &self.tests_dir_path,
+                &self.compile_patterns,
+                &log_path,
+                self.compile_timeout,
+            )
+        });
+
+        let report = self.compile_report.clone().unwrap();
+        let primary = Error::Compile(CompileError {
+            command: report.command,
+            output: report.output,
+        });
+        match res {
+            Ok(CompileResult::Error) => (),
+            Ok(CompileResult::Timeout) => {
+                return Err(Error::Timeout(self.compile_timeout));
+            }
+            // the recompile could not run or did not fail
+            _ => return Err(primary),
+        }
+
+        let output = std::fs::read(&log_path)
+            .map(|log| String::from_utf8_lossy(&log).into_owned())
+            .unwrap_or_default();
+        if output.trim().is_empty() {
+            return Err(primary);
+        }
+        Err(Error::Compile(CompileError { command, output }))
+    }
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.status == TestStatus::Passed
+    }
+
+    fn with_output_metrics(mut self, output: &ProcessOutput) -> Self {
+        let count_lines =
+            |buf: &[u8]| buf.iter().filter(|&&b| b == b'\n').count();
+
+        self.stdout_bytes = output.stdout_len;
+        self.stdout_lines = count_lines(&output.stdout);
+        self.stderr_bytes = output.stderr_len;
+        self.stderr_lines = count_lines(&output.stderr);
+        self.truncated_by_timeout = output.truncated_by_timeout;
+        self.exit_code = output.status.as_ref().ok().and_then(|s| s.code());
+        self
+    }
+}
+
+impl SuiteResults {
+    pub fn total(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn passed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| outcome.passed())
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| {
+                matches!(
+                    outcome.status,
+                    TestStatus::Failed | TestStatus::Errored
+                )
+            })
+            .count()
+    }
 }
 
 impl<'a> TestManager<'a> {
-    pub fn run_tests(&self) -> Vec<(&str, bool)> {
-        self.active_tests
+    pub fn stats(results: &[(String, TestOutcome)]) -> RunStats {
+        let count = |status: TestStatus| {
+            results.iter().filter(|(_, o)| o.status == status).count()
+        };
+        let passed = count(TestStatus::Passed);
+        let failed = count(TestStatus::Failed);
+        let skipped = count(TestStatus::Skipped);
+        let xfailed = count(TestStatus::XFail);
+        let xpassed = count(TestStatus::XPass);
+        let errored = count(TestStatus::Errored);
+
+        let mut durations: Vec<Duration> = results
             .iter()
-            .map(|(_, test)| {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                let outcome = rt.block_on(
-                    test.run(&self.tests_dir_path, self.startup_delay),
-                );
-                (test.name.as_str(), outcome)
+            .filter(|(_, o)| o.status != TestStatus::Skipped)
+            .map(|(_, o)| o.duration)
+            .collect();
+        durations.sort();
+
+        let executed = durations.len();
+        let total_duration: Duration = durations.iter().sum();
+        let (mean_duration, median_duration) = match executed {
+            0 => (Duration::ZERO, Duration::ZERO),
+            n => {
+                let median = match n % 2 {
+                    0 => (durations[n / 2 - 1] + durations[n / 2]) / 2,
+                    _ => durations[n / 2],
+                };
+                (total_duration / n as u32, median)
+            }
+        };
+
+        // expected failures don't count against the pass rate
+        let pass_rate = match executed - xfailed - xpassed {
+            0 => 0.0,
+            n => passed as f64 / n as f64,
+        };
+
+        RunStats {
+            total: results.len(),
+            passed,
+            failed,
+            skipped,
+            xfailed,
+            xpassed,
+            errored,
+            pass_rate,
+            total_duration,
+            mean_duration,
+            median_duration,
+        }
+    }
+}
+
+impl<'a> TestManager<'a> {
+    fn run_test(&self, test: &Test) -> TestOutcome {
+        self.run_test_in(test, &self.tests_dir_path)
+    }
+
+    fn run_test_in(&self, test: &Test, dir: &PathBuf) -> TestOutcome {
+        test.run_blocking(dir, self.startup_delay)
+    }
+
+    pub fn run_tests(&self) -> Vec<(String, TestOutcome)> {
+        self.run_ordered(&self.tests_dir_path)
+    }
+
+    // the tests with each one's dependencies before it, otherwise in
+    // instantiation order
+    fn dependency_order(tests: &IndexMap<String, Test>) -> Vec<&Test> {
+        fn visit<'t>(
+            test: &'t Test,
+            tests: &'t IndexMap<String, Test>,
+            path: &mut Vec<&'t str>,
+            order: &mut Vec<&'t Test>,
+        ) {
+            if order.iter().any(|t| t.name == test.name) {
+                return;
+            }
+            if let Some(start) = path.iter().position(|&n| n == test.name) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(&test.name);
+                panic!("[-] Dependency cycle: {}", cycle.join(" -> "));
+            }
+
+            path.push(&test.name);
+            for dependency in &test.dependencies {
+                if let Some(dependency) = tests.get(dependency) {
+                    visit(dependency, tests, path, order);
+                }
+            }
+            path.pop();
+            order.push(test);
+        }
+
+        let mut order = Vec::new();
+        for test in tests.values() {
+            visit(test, tests, &mut Vec::new(), &mut order);
+        }
+        order
+    }
+
+    fn run_ordered(&self, dir: &PathBuf) -> Vec<(String, TestOutcome)> {
+        Self::run_ordered_where(
+            &self.active_tests,
+            Vec::new(),
+            |_| true,
+            |test| self.run_test_in(test, dir),
+        )
+    }
+
+    // appends the outcomes of the tests `include` selects, each run by
+    // `run` unless a dependency did not pass, to `results`, whose earlier
+    // outcomes count for the dependencies
+    fn run_ordered_where<I, R>(
+        tests: &IndexMap<String, Test>,
+        mut results: Vec<(String, TestOutcome)>,
+        include: I,
+        mut run: R,
+    ) -> Vec<(String, TestOutcome)>
+    where
+        I: Fn(&Test) -> bool,
+        R: FnMut(&Test) -> TestOutcome,
+    {
+        for test in Self::dependency_order(tests) {
+            if !include(test) {
+                continue;
+            }
+
+            let failed = test.dependencies.iter().find(|dependency| {
+                !results.iter().any(|(name, outcome)| {
+                    name == *dependency
+                        && matches!(
+                            outcome.status,
+                            TestStatus::Passed | TestStatus::XPass
+                        )
+                })
+            });
+
+            let outcome = match failed {
+                Some(dependency) => {
+                    println!(
+                        "[!] Skipping {} test, dependency {} did not pass",
+                        test.name, dependency
+                    );
+                    println!();
+                    test.skipped("dependency failed")
+                }
+                None => run(test),
+            };
+            results.push((test.name.clone(), outcome));
+        }
+        results
+    }
+
+    pub fn run_single(&self, test_name: &str) -> Result<TestOutcome, Error> {
+        self.run_single_with_args(test_name, &[])
+    }
+
+    // the test's dependencies run first, without `extra_args`; the test is
+    // skipped unless they all pass
+    pub fn run_single_with_args(
+        &self,
+        test_name: &str,
+        extra_args: &[String],
+    ) -> Result<TestOutcome, Error> {
+        let test = match self.active_tests.get(test_name) {
+            Some(test) => test,
+            None => return Err(Error::UnknownTest(test_name.to_string())),
+        };
+
+        let mut needed = vec![test.name.as_str()];
+        let mut pending = vec![test];
+        while let Some(test) = pending.pop() {
+            for dependency in &test.dependencies {
+                if let Some(dependency) = self.active_tests.get(dependency) {
+                    if !needed.contains(&dependency.name.as_str()) {
+                        needed.push(&dependency.name);
+                        pending.push(dependency);
+                    }
+                }
+            }
+        }
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let results = Self::run_ordered_where(
+            &self.active_tests,
+            Vec::new(),
+            |test| needed.contains(&test.name.as_str()),
+            |test| {
+                let args = match test.name == test_name {
+                    true => extra_args,
+                    false => &[],
+                };
+                rt.block_on(test.run_with_args(
+                    &self.tests_dir_path,
+                    self.startup_delay,
+                    args,
+                ))
+            },
+        );
+        let (_, outcome) = results
+            .into_iter()
+            .find(|(name, _)| name == test_name)
+            .unwrap();
+        Ok(outcome)
+    }
+
+    // runs every active test and archives the outcomes along with the
+    // captured output, see report::archive::replay
+    pub fn record_run(
+        &self,
+        path: &Path,
+    ) -> Result<Vec<(String, TestOutcome)>, Error> {
+        let mut captured = HashMap::new();
+        let results = Self::run_ordered_where(
+            &self.active_tests,
+            Vec::new(),
+            |_| true,
+            |test| {
+                *test.recorded.lock().unwrap() = Some(Default::default());
+                let outcome = self.run_test(test);
+                let output =
+                    test.recorded.lock().unwrap().take().unwrap_or_default();
+                captured.insert(test.name.clone(), output);
+                outcome
+            },
+        );
+
+        // skipped tests archive without output
+        let tests = results
+            .into_iter()
+            .map(|(name, outcome)| {
+                let (stdout, stderr) =
+                    captured.remove(&name).unwrap_or_default();
+                ArchivedTest {
+                    cmd_args: self.active_tests[&name].cmd_args.clone(),
+                    name,
+                    outcome,
+                    stdout,
+                    stderr,
+                }
             })
-            .collect()
+            .collect();
+
+        let archive = RunArchive::new(tests);
+        archive.write(path)?;
+        Ok(archive.results())
+    }
+
+    pub fn run_tests_in(&self, dir: &Path) -> Vec<(String, TestOutcome)> {
+        if !dir.exists() {
+            panic!("[-] Tests directory not found: {:?}", dir);
+        }
+
+        self.run_ordered(&dir.to_path_buf())
+    }
+
+    // reruns the tests that failed or errored in `previous`, e.g. the
+    // results of an earlier run_tests, dependencies first; the other
+    // outcomes there count for the dependencies
+    pub fn rerun_failed(
+        &self,
+        previous: &[(String, TestOutcome)],
+    ) -> Vec<(String, TestOutcome)> {
+        let (failed, kept): (Vec<_>, Vec<_>) =
+            previous.iter().cloned().partition(|(_, outcome)| {
+                matches!(
+                    outcome.status,
+                    TestStatus::Failed | TestStatus::Errored
+                )
+            });
+        for (name, _) in &failed {
+            if !self.active_tests.contains_key(name) {
+                println!("[!] Not rerunning {}, it is not active", name);
+            }
+        }
+
+        let seeded = kept.len();
+        let mut results = Self::run_ordered_where(
+            &self.active_tests,
+            kept,
+            |test| failed.iter().any(|(name, _)| *name == test.name),
+            |test| self.run_test(test),
+        );
+        results.split_off(seeded)
+    }
+
+    // dependencies outside the suite are not run and count as not passed
+    pub fn run_suite(&self, suite: &str) -> Vec<(String, TestOutcome)> {
+        Self::run_ordered_where(
+            &self.active_tests,
+            Vec::new(),
+            |test| test.suite == suite,
+            |test| self.run_test(test),
+        )
+    }
+
+    // groups the outcomes of a run, e.g. of run_tests, by their suite in
+    // order of first appearance; nothing is run again
+    pub fn results_by_suite(
+        &self,
+        results: &[(String, TestOutcome)],
+    ) -> IndexMap<String, SuiteResults> {
+        let mut suites: IndexMap<String, SuiteResults> = IndexMap::new();
+        for (name, outcome) in results {
+            suites
+                .entry(outcome.suite.clone())
+                .or_insert_with(|| SuiteResults { results: Vec::new() })
+                .results
+                .push((name.clone(), outcome.clone()));
+        }
+        suites
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // fresh tests from every template for one submission, so no agent,
+    // context or log is shared with another one; ports are those of the
+    // active tests of the same name
+    fn submission_tests(
+        &self,
+        dir: &Path,
+        log_dir: Option<&Path>,
+    ) -> (IndexMap<String, Test>, Vec<String>) {
+        let mut tests = IndexMap::new();
+        let mut unported = Vec::new();
+        for template in self.templates.values() {
+            let mut port = self
+                .active_tests
+                .get(&template.name)
+                .and_then(|test| test.port());
+            let needs_port =
+                template.require_communicator || template.templates_port();
+            if needs_port && port.is_none() {
+                println!(
+                    "[!] Skipping {} in the batch, it has no active test giving its port",
+                    template.name
+                );
+                // instantiated only to report it as skipped
+                unported.push(template.name.clone());
+                port = Some(0);
+            }
+
+            // the copy an isolated test runs in is removed with its logs
+            let results_dir = match (log_dir, template.isolated) {
+                (None, true) => Some(
+                    std::fs::canonicalize(dir)
+                        .unwrap_or_else(|_| dir.to_path_buf()),
+                ),
+                (log_dir, _) => log_dir.map(Path::to_path_buf),
+            };
+            let mut test = template.instantiate_in(
+                &template.name,
+                port,
+                &HashMap::new(),
+                results_dir.as_deref(),
+            );
+            if template.timeout.is_none() {
+                test.timeout = self.default_timeout;
+            }
+            test.log_budget = self.log_budget.clone();
+            tests.insert(test.name.clone(), test);
+        }
+        (tests, unported)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn grade_submission(
+        mut tests: IndexMap<String, Test>,
+        unported: &[String],
+        port_lock: &std::sync::Mutex<()>,
+        startup_delay: u64,
+        compile_cmd: Option<&str>,
+        compile_patterns: &CompilePatterns,
+        compile_timeout: Duration,
+        dir: &PathBuf,
+        log_dir: &Path,
+    ) -> Vec<(String, TestOutcome)> {
+        println!("[*] Grading submission: {:?}", dir);
+        // interpreted submissions have nothing to compile
+        let compiled = match compile_cmd {
+            Some(cmd) => {
+                let log_path = log_dir.join("compilation_output.txt");
+                let started = Instant::now();
+                let res = std::panic::catch_unwind(|| {
+                    compile_logged(
+                        cmd,
+                        dir,
+                        compile_patterns,
+                        &log_path,
+                        compile_timeout,
+                    )
+                });
+                if let Ok(res) = res {
+                    let report = CompileReport {
+                        command: cmd.to_string(),
+                        status: res.as_str().to_string(),
+                        output: std::fs::read(&log_path)
+                            .map(|log| {
+                                String::from_utf8_lossy(&log).into_owned()
+                            })
+                            .unwrap_or_default(),
+                        duration: started.elapsed(),
+                    };
+                    for test in tests.values_mut() {
+                        test.context.compile_report = Some(report.clone());
+                    }
+                }
+                matches!(res, Ok(res) if !res.failed())
+            }
+            None => true,
+        };
+        if !compiled {
+            println!("[-] Compilation failed in {:?}", dir);
+        }
+
+        Self::run_ordered_where(
+            &tests,
+            Vec::new(),
+            |_| true,
+            |test| {
+                if unported.contains(&test.name) {
+                    return test.skipped("no port");
+                }
+                if !compiled {
+                    return TestOutcome {
+                        reason: Some(String::from("compilation failed")),
+                        ..test.outcome(false)
+                    };
+                }
+                // the port is shared by every submission
+                let _port = test.port().map(|_| {
+                    port_lock.lock().unwrap_or_else(|e| e.into_inner())
+                });
+                test.run_isolated(dir, startup_delay)
+            },
+        )
+    }
+
+    // each submission gets its own tests and, under with_results_dir, its
+    // own log directory named after it, so the submissions' directory names
+    // must then differ
+    pub fn run_batch(
+        &self,
+        dirs: &[PathBuf],
+    ) -> HashMap<PathBuf, Vec<(String, TestOutcome)>> {
+        if self.results_dir.is_some() {
+            let mut names = std::collections::HashSet::new();
+            for dir in dirs {
+                if !names.insert(dir.file_name()) {
+                    panic!(
+                        "[-] Submission directory names must differ: {:?}",
+                        dir
+                    );
+                }
+            }
+        }
+
+        let mut submissions = Vec::new();
+        for dir in dirs.iter().rev() {
+            let log_dir = self.results_dir.as_ref().map(|results_dir| {
+                let log_dir =
+                    results_dir.join(dir.file_name().unwrap_or_default());
+                std::fs::create_dir_all(&log_dir).unwrap_or_else(|e| {
+                    panic!("[-] Failed to create results directory: {}", e)
+                });
+                log_dir
+            });
+            let (tests, unported) =
+                self.submission_tests(dir, log_dir.as_deref());
+            submissions.push((dir, log_dir, tests, unported));
+        }
+
+        let startup_delay = self.startup_delay;
+        let compile_cmd = self.compile_command.as_deref();
+        let compile_patterns = &self.compile_patterns;
+        let compile_timeout = self.compile_timeout;
+        let workers = self.batch_concurrency.min(dirs.len());
+        // taken from the back, the first directory first
+        let submissions = std::sync::Mutex::new(submissions);
+        let results = std::sync::Mutex::new(HashMap::new());
+        let port_lock = std::sync::Mutex::new(());
+
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let next = submissions.lock().unwrap().pop();
+                    let Some((dir, log_dir, tests, unported)) = next else {
+                        break;
+                    };
+
+                    // a panic grading one submission fails only its tests
+                    let aborted: Vec<(String, TestOutcome)> = tests
+                        .values()
+                        .map(|test| {
+                            let outcome = TestOutcome {
+                                reason: Some(String::from("grading aborted")),
+                                ..test.outcome(false)
+                            };
+                            (test.name.clone(), outcome)
+                        })
+                        .collect();
+                    let outcomes =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            Self::grade_submission(
+                                tests,
+                                &unported,
+                                &port_lock,
+                                startup_delay,
+                                compile_cmd,
+                                compile_patterns,
+                                compile_timeout,
+                                dir,
+                                log_dir.as_deref().unwrap_or(dir),
+                            )
+                        }))
+                        .unwrap_or_else(|_| {
+                            println!("[-] Grading {:?} aborted", dir);
+                            aborted
+                        });
+                    let mut results =
+                        results.lock().unwrap_or_else(|e| e.into_inner());
+                    results.insert(dir.clone(), outcomes);
+                });
+            }
+        });
+
+        results.into_inner().unwrap()
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // a sanity check for after the suite, every process the tests started
+    // should have been reaped by now; returns the children still around
+    pub fn assert_no_leaked_children(&self) -> Vec<ChildProcess> {
+        let children = child_processes(std::process::id() as i32);
+        for child in &children {
+            match child.zombie {
+                true => println!(
+                    "[-] Child process {} ({}) was never reaped",
+                    child.pid, child.command
+                ),
+                false => println!(
+                    "[-] Child process {} ({}) is still running",
+                    child.pid, child.command
+                ),
+            }
+        }
+        children
+    }
+}
+
+impl<'a> TestManager<'a> {
+    // runs the smoke tests first and the rest only when all of them passed
+    // (or xpassed), otherwise the rest are reported as skipped
+    pub fn run_smoke_then_full(&self) -> Vec<(String, TestOutcome)> {
+        let tests = &self.active_tests;
+        let run = |test: &Test| self.run_test(test);
+        let results =
+            Self::run_ordered_where(tests, Vec::new(), |t| t.smoke, run);
+        let smoke_failed = results.iter().any(|(_, outcome)| {
+            !matches!(outcome.status, TestStatus::Passed | TestStatus::XPass)
+        });
+        if !smoke_failed {
+            return Self::run_ordered_where(tests, results, |t| !t.smoke, run);
+        }
+
+        println!("[-] Smoke tests failed, skipping the remaining tests");
+        println!();
+        let mut results = results;
+        for test in Self::dependency_order(tests) {
+            if !test.smoke {
+                let outcome = test.skipped("smoke tests failed");
+                results.push((test.name.clone(), outcome));
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExitCodeAgent, SoftAssertAgent};
+
+    // a tests directory of its own, removed by the caller
+    fn tests_dir() -> PathBuf {
+        let dir = unique_temp_dir("tests-lib-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manager(dir: &Path) -> TestManager<'static> {
+        TestManager::new("unit", dir.to_str().unwrap(), 0)
+    }
+
+    fn template(name: &str, args: &str) -> TestTemplateBuilder {
+        TestTemplateBuilder::new(name)
+            .args_template(args)
+            .log_output(false)
+            .agent(Box::new(|| Box::new(ExitCodeAgent::new(&[0]))))
+    }
+
+    // leaves the verdict to the harness' own checks
+    struct PassAgent;
+
+    #[async_trait]
+    impl TestAgent for PassAgent {
+        async fn validate(
+            &self,
+            _args: &Vec<String>,
+            _communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            true
+        }
+    }
+
+    fn passing(name: &str, args: &str) -> TestTemplateBuilder {
+        template(name, args).agent(Box::new(|| Box::new(PassAgent)))
+    }
+
+    // the outcome of running one test made from `template`
+    fn run_one(dir: &Path, template: TestTemplateBuilder) -> TestOutcome {
+        let mut manager = manager(dir);
+        let name = manager.register_template(template.build());
+        manager.instantiate_test(&name, None);
+        manager.run_tests().remove(0).1
+    }
+
+    // collects the `phase` field of every event
+    struct PhaseRecorder(Arc<std::sync::Mutex<Vec<String>>>);
+
+    struct PhaseVisitor<'v>(&'v mut Option<String>);
+
+    impl tracing::field::Visit for PhaseVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            if field.name() == "phase" {
+                *self.0 = Some(value.to_string());
+            }
+        }
+
+        fn record_debug(
+            &mut self,
+            _field: &tracing::field::Field,
+            _value: &dyn std::fmt::Debug,
+        ) {
+        }
+    }
+
+    impl tracing::Subscriber for PhaseRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(
+            &self,
+            _span: &tracing::span::Attributes<'_>,
+        ) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(
+            &self,
+            _span: &tracing::span::Id,
+            _values: &tracing::span::Record<'_>,
+        ) {
+        }
+
+        fn record_follows_from(
+            &self,
+            _span: &tracing::span::Id,
+            _follows: &tracing::span::Id,
+        ) {
+        }
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut phase = None;
+            event.record(&mut PhaseVisitor(&mut phase));
+            if let Some(phase) = phase {
+                self.0.lock().unwrap().push(phase);
+            }
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn phases_are_traced_in_order() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(template("echo", "echo hi").build());
+        manager.instantiate_test("echo", None);
+
+        let phases = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = PhaseRecorder(Arc::clone(&phases));
+        let results =
+            tracing::subscriber::with_default(recorder, || manager.run_tests());
+        assert!(results[0].1.passed());
+        assert_eq!(
+            *phases.lock().unwrap(),
+            [
+                "spawn",
+                "ready",
+                "process-exit",
+                "validate-start",
+                "validate-end"
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn termination_expectations() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("crash.sh"), "kill -SEGV $$").unwrap();
+        std::fs::write(dir.join("exit.sh"), "exit $1").unwrap();
+        let crash = || passing("crash", "sh crash.sh");
+        let exit = |code: i32| passing("exit", &format!("sh exit.sh {}", code));
+
+        assert!(run_one(&dir, crash().expect_signal(libc::SIGSEGV)).passed());
+        assert!(!run_one(&dir, crash().expect_signal(libc::SIGABRT)).passed());
+        assert!(!run_one(&dir, crash().expected_exit_code(0)).passed());
+        assert!(!run_one(&dir, exit(0).expect_signal(libc::SIGSEGV)).passed());
+        assert!(run_one(&dir, exit(3).expected_exit_codes(2..=4)).passed());
+        assert!(!run_one(&dir, exit(5).expected_exit_codes(2..=4)).passed());
+        // the last expectation set wins
+        let last = exit(0).expect_signal(libc::SIGSEGV).expected_exit_code(0);
+        assert!(run_one(&dir, last).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn valgrind_args_replace_the_defaults() {
+        let default = template("memcheck", "./prog").valgrind(true).build();
+        let argv = default.instantiate(None).cmd_args().to_vec();
+        assert_eq!(argv[0], "valgrind");
+        assert!(default_valgrind_args().iter().all(|arg| argv.contains(arg)));
+        assert_eq!(argv.last().unwrap(), "./prog");
+
+        let custom = template("helgrind", "./prog")
+            .valgrind(true)
+            .valgrind_args(vec![String::from("--tool=helgrind")])
+            .valgrind_suppressions(PathBuf::from("known.supp"))
+            .build()
+            .instantiate(None);
+        let argv = custom.cmd_args();
+        assert!(argv.contains(&String::from("--tool=helgrind")));
+        assert!(argv.contains(&String::from("--suppressions=known.supp")));
+        assert!(!argv.contains(&String::from("--leak-check=full")));
+        assert_eq!(custom.valgrind_error_code, None);
+    }
+
+    #[test]
+    fn max_allocs_caps_the_heap_usage_in_the_log() {
+        let log = "\
+==1== total heap usage: 3 allocs, 3 frees, 96 bytes allocated
+==1== ERROR SUMMARY: 0 errors from 0 contexts (suppressed: 0 from 0)
+";
+        let allocs = ValgrindSummary::parse(log).map(|summary| summary.allocs);
+        assert_eq!(allocs, Some(3));
+
+        let capped = |max_allocs| {
+            let template = template("memcheck", "./prog").valgrind(true);
+            template.max_allocs(max_allocs).build().instantiate(None)
+        };
+        assert!(capped(3).check_allocs(allocs));
+        assert!(!capped(2).check_allocs(allocs));
+        // nothing parsed, nothing to cap
+        assert!(capped(2).check_allocs(None));
+    }
+
+    #[test]
+    fn descriptions_reach_the_outcome_and_reports() {
+        let dir = tests_dir();
+        let description = "Checks that the server handles concurrent clients";
+        let described = template("described", "true").description(description);
+        let outcome = run_one(&dir, described);
+        assert_eq!(outcome.description, description);
+        assert_eq!(run_one(&dir, template("terse", "true")).description, "");
+
+        let results = [(String::from("described"), outcome)];
+        let environment = crate::report::collect_environment();
+        let report = crate::report::json::to_value(&results, &environment);
+        assert_eq!(report["results"][0]["description"], description);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // records a measured latency for the reports
+    struct LatencyAgent;
+
+    #[async_trait]
+    impl TestAgent for LatencyAgent {
+        async fn validate_with_context(
+            &self,
+            context: &TestContext,
+            _args: &Vec<String>,
+            _communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            context.record("latency_ms", 12);
+            true
+        }
+    }
+
+    #[test]
+    fn agent_metadata_reaches_the_reports() {
+        let dir = tests_dir();
+        let timed = template("timed", "true")
+            .agent(Box::new(|| Box::new(LatencyAgent)));
+        let outcome = run_one(&dir, timed);
+        assert_eq!(outcome.metadata["latency_ms"], "12");
+
+        let results = [(String::from("timed"), outcome)];
+        let environment = crate::report::collect_environment();
+        let report = crate::report::json::to_value(&results, &environment);
+        assert_eq!(report["results"][0]["metadata"]["latency_ms"], "12");
+        let csv = crate::report::csv::to_string(&results);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",latency_ms=12"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn run_tests_in_uses_the_given_directory() {
+        let root = tests_dir();
+        for (name, code) in [("good", 0), ("bad", 1)] {
+            std::fs::create_dir(root.join(name)).unwrap();
+            let script = format!("exit {}", code);
+            std::fs::write(root.join(name).join("prog.sh"), script).unwrap();
+        }
+
+        let mut manager = manager(&root);
+        manager.register_template(template("prog", "sh prog.sh").build());
+        manager.instantiate_test("prog", None);
+        assert!(manager.run_tests_in(&root.join("good"))[0].1.passed());
+        assert!(!manager.run_tests_in(&root.join("bad"))[0].1.passed());
+
+        let missing = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            manager.run_tests_in(&root.join("missing"))
+        }));
+        assert!(missing.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn the_default_timeout_fills_unset_timeouts() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir).with_default_timeout(1);
+        manager.register_template(template("unset", "sleep 3").build());
+        manager.register_template(
+            template("zero", "sleep 1.2").timeout(0).build(),
+        );
+        manager.instantiate_test("unset", None);
+        manager.instantiate_test("zero", None);
+        assert_eq!(manager.active_tests["unset"].timeout(), 1);
+        assert_eq!(manager.active_tests["zero"].timeout(), 0);
+
+        // a timeout of 0 runs unbounded despite the default
+        let results = manager.run_tests();
+        assert!(!results[0].1.passed());
+        assert!(results[1].1.passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interpreted_scripts_run_without_an_elf() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("solution.py"), "print('hello')").unwrap();
+        let python = || Runner::Interpreter(String::from("python3"));
+
+        let script = template("script", "solution.py")
+            .runner(python())
+            .require_elf(true)
+            .expected_stdout("hello\n");
+        assert!(run_one(&dir, script).passed());
+        let missing = template("missing", "missing.py").runner(python());
+        assert!(!run_one(&dir, missing).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn preserved_paths_are_restored_after_each_test() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("fixture.txt"), "kept\n").unwrap();
+        let preserved = vec![PathBuf::from("fixture.txt")];
+
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("deletes", "rm fixture.txt")
+                .preserve_paths(preserved)
+                .build(),
+        );
+        manager.register_template(
+            template("reads", "cat fixture.txt")
+                .expected_stdout("kept\n")
+                .build(),
+        );
+        manager.instantiate_test("deletes", None);
+        manager.instantiate_test("reads", None);
+
+        let results = manager.run_tests();
+        assert!(results.iter().all(|(_, outcome)| outcome.passed()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn outcomes_count_output_bytes_and_lines() {
+        let dir = tests_dir();
+        let script = "printf 'a\\nbb\\nc\\n'; printf err >&2";
+        std::fs::write(dir.join("out.sh"), script).unwrap();
+
+        let outcome = run_one(&dir, template("out", "sh out.sh"));
+        assert_eq!((outcome.stdout_bytes, outcome.stdout_lines), (7, 3));
+        assert_eq!((outcome.stderr_bytes, outcome.stderr_lines), (3, 0));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn valgrind_error_exit_code_fails_the_test() {
+        let exited = |code: i32| {
+            let status = Ok(ExitStatus::from_raw(code << 8));
+            ProcessOutput::new(Vec::new(), Vec::new(), status)
+        };
+        let plain = template("plain", "./prog").build().instantiate(None);
+        assert!(plain.on_validate(&exited(1)));
+
+        let memcheck = template("memcheck", "./prog").valgrind(true).build();
+        let memcheck = memcheck.instantiate(None);
+        assert_eq!(memcheck.valgrind_error_code, Some(1));
+        assert!(!memcheck.on_validate(&exited(1)));
+        assert!(memcheck.on_validate(&exited(0)));
+    }
+
+    #[test]
+    fn run_single_runs_only_the_named_test() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        for name in ["a", "b", "c"] {
+            let args = format!("touch {}.ran", name);
+            manager.register_template(template(name, &args).build());
+            manager.instantiate_test(name, None);
+        }
+
+        assert!(manager.run_single("b").unwrap().passed());
+        let ran: Vec<bool> = ["a", "b", "c"]
+            .iter()
+            .map(|name| dir.join(format!("{}.ran", name)).exists())
+            .collect();
+        assert_eq!(ran, [false, true, false]);
+        assert!(matches!(
+            manager.run_single("missing"),
+            Err(Error::UnknownTest(name)) if name == "missing"
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // an outcome of the given status that took `millis`
+    fn outcome(status: TestStatus, millis: u64) -> (String, TestOutcome) {
+        let test = template("t", "true").build().instantiate(None);
+        let outcome = TestOutcome {
+            status,
+            duration: Duration::from_millis(millis),
+            ..test.outcome(status == TestStatus::Passed)
+        };
+        (format!("{:?}", status), outcome)
+    }
+
+    #[test]
+    fn stats_summarize_a_known_mix() {
+        let results = [
+            outcome(TestStatus::Passed, 10),
+            outcome(TestStatus::Passed, 40),
+            outcome(TestStatus::Passed, 20),
+            outcome(TestStatus::Failed, 30),
+            outcome(TestStatus::Skipped, 0),
+            outcome(TestStatus::XFail, 50),
+        ];
+        let stats = TestManager::stats(&results);
+        assert_eq!(
+            stats,
+            RunStats {
+                total: 6,
+                passed: 3,
+                failed: 1,
+                skipped: 1,
+                xfailed: 1,
+                xpassed: 0,
+                errored: 0,
+                pass_rate: 0.75,
+                total_duration: Duration::from_millis(150),
+                mean_duration: Duration::from_millis(30),
+                median_duration: Duration::from_millis(30),
+            }
+        );
+        assert_eq!(TestManager::stats(&[]).pass_rate, 0.0);
+    }
+
+    #[test]
+    fn valgrind_verbosity_is_off_by_default() {
+        let argv = |verbose: Option<bool>| {
+            let mut memcheck = template("memcheck", "./prog").valgrind(true);
+            if let Some(verbose) = verbose {
+                memcheck = memcheck.valgrind_verbose(verbose);
+            }
+            memcheck.build().instantiate(None).cmd_args().to_vec()
+        };
+        let verbose_flags = |argv: &[String]| {
+            argv.iter()
+                .filter(|arg| *arg == "--verbose" || *arg == "-v")
+                .count()
+        };
+        assert_eq!(verbose_flags(&argv(None)), 0);
+        assert_eq!(verbose_flags(&argv(Some(false))), 0);
+        assert_eq!(verbose_flags(&argv(Some(true))), 2);
+    }
+
+    #[test]
+    fn logs_go_to_the_results_dir() {
+        let dir = tests_dir();
+        let results_dir = dir.join("results");
+        let mut manager = manager(&dir).with_results_dir(&results_dir);
+        manager.register_template(
+            template("echo", "echo logged").log_output(true).build(),
+        );
+        manager.instantiate_test("echo", None);
+        assert!(manager.run_tests()[0].1.passed());
+
+        let log =
+            std::fs::read_to_string(results_dir.join("output - echo.txt"));
+        assert_eq!(log.unwrap(), "logged\n");
+        assert!(!dir.join("output - echo.txt").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn logs_stop_once_the_budget_is_spent() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir).with_log_budget(2500);
+        let names = ["a", "b", "c", "d"];
+        for name in names {
+            let chatty =
+                passing(name, "head -c 1000 /dev/zero").log_output(true);
+            manager.register_template(chatty.build());
+            manager.instantiate_test(name, None);
+        }
+
+        let results = manager.run_tests();
+        assert!(results.iter().all(|(_, outcome)| outcome.passed()));
+        let logged: Vec<bool> = names
+            .iter()
+            .map(|name| dir.join(format!("output - {}.txt", name)).exists())
+            .collect();
+        assert_eq!(logged, [true, true, false, false]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extra_args_are_appended_at_run_time() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("echo", "echo run")
+                .expected_stdout("run --seed 42\n")
+                .build(),
+        );
+        manager.instantiate_test("echo", None);
+
+        let seed = [String::from("--seed"), String::from("42")];
+        assert!(manager
+            .run_single_with_args("echo", &seed)
+            .unwrap()
+            .passed());
+        // the instantiated args are left as they were
+        assert_eq!(manager.active_tests["echo"].cmd_args(), ["echo", "run"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn normalizers_rewrite_stdout_before_comparison() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("stamp.sh"), "date +%s%N; echo result 42")
+            .unwrap();
+        let drop_timestamps = |stdout: &[u8]| {
+            let stdout = String::from_utf8_lossy(stdout);
+            let timestamp = regex::Regex::new(r"(?m)^\d+\n").unwrap();
+            timestamp.replace_all(&stdout, "").into_owned().into_bytes()
+        };
+
+        let stamped = || template("stamp", "sh stamp.sh");
+        let golden = "result 42\n";
+        let normalized = stamped()
+            .normalizer(drop_timestamps)
+            .expected_stdout(golden);
+        assert!(run_one(&dir, normalized).passed());
+        assert!(!run_one(&dir, stamped().expected_stdout(golden)).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn xfail_tests_report_xfail_and_xpass() {
+        let dir = tests_dir();
+        let xfailing = run_one(&dir, template("fails", "false").xfail(true));
+        assert_eq!(xfailing.status, TestStatus::XFail);
+
+        let xpassing = run_one(&dir, template("passes", "true").xfail(true));
+        assert_eq!(xpassing.status, TestStatus::XPass);
+        assert_eq!(xpassing.points, 0.0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn leaked_descriptors_fail_under_max_open_fds() {
+        let dir = tests_dir();
+        let leaks = "\
+import time
+files = [open('fds.py') for _ in range(50)]
+time.sleep(0.3)
+";
+        std::fs::write(dir.join("fds.py"), leaks).unwrap();
+        let capped =
+            |max: usize| template("fds", "python3 fds.py").max_open_fds(max);
+
+        assert!(!run_one(&dir, capped(10)).passed());
+        assert!(run_one(&dir, capped(200)).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_and_run_stops_at_a_failed_compile() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(template("runs", "./prog").build());
+        manager.instantiate_test("runs", None);
+
+        let broken = "echo error: broken >&2 # -o prog";
+        match manager.build_and_run(broken) {
+            Err(Error::Compile(error)) => {
+                assert_eq!(error.command, broken);
+                assert_eq!(error.output, "error: broken\n");
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+
+        let builds = "printf '#!/bin/sh\\n' > prog && chmod +x prog # -o prog";
+        let results = manager.build_and_run(builds).unwrap();
+        assert!(results[0].1.passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn slow_tests_are_flagged_but_pass() {
+        let dir = tests_dir();
+        let over = run_one(&dir, template("over", "sleep 0.2").soft_timeout(0));
+        assert!(over.passed() && over.slow);
+
+        let within = run_one(&dir, template("within", "true").soft_timeout(5));
+        assert!(within.passed() && !within.slow);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_timeout_signal_lets_a_program_clean_up() {
+        let dir = tests_dir();
+        let traps =
+            "trap 'echo cleaned; exit 0' INT\nwhile :; do sleep 0.1; done";
+        std::fs::write(dir.join("traps.sh"), traps).unwrap();
+        let traps = || template("traps", "sh traps.sh").timeout(1);
+
+        let interrupted = traps()
+            .timeout_signal(libc::SIGINT)
+            .expected_stdout("cleaned\n");
+        assert!(run_one(&dir, interrupted).passed());
+        assert!(!run_one(&dir, traps()).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn asan_reports_fail_the_test() {
+        let dir = tests_dir();
+        let source = "#include <stdlib.h>\n\
+            int main(void) { int *p = malloc(4); free(p); return *p != 0; }\n";
+        std::fs::write(dir.join("uaf.c"), source).unwrap();
+        let status = std::process::Command::new("gcc")
+            .args(["-fsanitize=address", "uaf.c", "-o", "uaf"])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let uaf = || passing("uaf", "./uaf");
+        assert!(run_one(&dir, uaf()).passed());
+        assert!(!run_one(&dir, uaf().detect_asan(true)).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn named_placeholders_are_substituted() {
+        let template = template(
+            "ports",
+            "./server --port {server_port} --admin {admin_port} {server_port}",
+        )
+        .build();
+        let substitutions = HashMap::from([
+            ("server_port".to_string(), "8080".to_string()),
+            ("admin_port".to_string(), "9090".to_string()),
+        ]);
+        let test = template.instantiate_with(None, &substitutions);
+        assert_eq!(
+            test.cmd_args(),
+            ["./server", "--port", "8080", "--admin", "9090", "8080"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unresolved placeholder {admin_prot}")]
+    fn an_unresolved_placeholder_is_rejected() {
+        let template = template("ports", "./server {admin_prot}").build();
+        let substitutions =
+            HashMap::from([("admin_port".to_string(), "9090".to_string())]);
+        template.instantiate_with(None, &substitutions);
+    }
+
+    #[test]
+    fn literal_braces_are_kept_as_written() {
+        let template = template("awk", "awk {print} {name}")
+            .literal_braces("print")
+            .build();
+        let substitutions =
+            HashMap::from([("name".to_string(), "input.txt".to_string())]);
+        let test = template.instantiate_with(None, &substitutions);
+        assert_eq!(test.cmd_args(), ["awk", "{print}", "input.txt"]);
+    }
+
+    #[test]
+    fn output_cut_off_by_the_timeout_is_flagged() {
+        let dir = tests_dir();
+        // exec, a forked sleep would hold stdout open past the kill
+        let script = "echo partial\nexec sleep 30";
+        std::fs::write(dir.join("stalls.sh"), script).unwrap();
+
+        let outcome =
+            run_one(&dir, passing("stalls", "sh stalls.sh").timeout(1));
+        assert!(outcome.truncated_by_timeout);
+        assert_eq!(outcome.stdout_bytes, "partial\n".len());
+        assert!(
+            !run_one(&dir, passing("echo", "echo done")).truncated_by_timeout
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn line_order_is_ignored_on_request() {
+        let dir = tests_dir();
+        let shuffled = || {
+            passing("shuffled", r"printf b\na\nc\n")
+                .expected_stdout("a\nb\nc\n")
+        };
+        assert!(!run_one(&dir, shuffled()).passed());
+        assert!(run_one(&dir, shuffled().ignore_line_order(true)).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_fatal_banner_fails_the_test_early() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("panics.sh"), "echo PANIC\nexec sleep 30")
+            .unwrap();
+        std::fs::write(dir.join("warns.sh"), "echo PANIC >&2\nexec sleep 30")
+            .unwrap();
+
+        let stdout = passing("panics", "sh panics.sh")
+            .timeout(20)
+            .fail_on_stdout_contains("PANIC");
+        let stderr = passing("warns", "sh warns.sh")
+            .timeout(20)
+            .fail_on_stderr_contains("PANIC");
+        for sentinel in [stdout, stderr] {
+            let outcome = run_one(&dir, sentinel);
+            assert!(!outcome.passed());
+            assert!(outcome.duration < Duration::from_secs(10));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pre_compile_steps_run_before_the_compile() {
+        let dir = tests_dir();
+        let mut generated = manager(&dir);
+        let codegen = "echo 'int main(void) { return 0; }' > main.c";
+        generated.run_pre_compile(&[codegen]).unwrap();
+        let compiled = generated.compile_assignment("gcc -Wall main.c -o main");
+        assert_eq!(compiled, "success");
+        assert!(dir.join("main").exists());
+
+        let failing = generated.run_pre_compile(&["false", "touch never"]);
+        assert!(matches!(failing, Err(Error::PreCompile(_))));
+        assert!(!dir.join("never").exists());
+        let log = std::fs::read_to_string(dir.join("pre_compile_output.txt"));
+        assert_eq!(log.unwrap(), "$ false\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_binary_that_cannot_start_errors_only_its_test() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("data.txt"), "not a program").unwrap();
+        let mut manager = manager(&dir);
+        for template in [
+            passing("noexec", "./data.txt"),
+            passing("missing", "./no-such-prog"),
+            passing("echo", "echo hi"),
+        ] {
+            let name = manager.register_template(template.build());
+            manager.instantiate_test(&name, None);
+        }
+
+        let results = manager.run_tests();
+        let statuses: Vec<(&str, TestStatus)> = results
+            .iter()
+            .map(|(name, outcome)| (name.as_str(), outcome.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("noexec", TestStatus::Errored),
+                ("missing", TestStatus::Errored),
+                ("echo", TestStatus::Passed),
+            ]
+        );
+        // the spawn error is the reason
+        let reason = results[1].1.reason.as_deref().unwrap();
+        assert!(reason.contains("not found"), "{}", reason);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn processes_run_at_the_requested_niceness() {
+        let dir = tests_dir();
+        // the 19th field of stat is the niceness
+        std::fs::write(dir.join("nice.sh"), "cut -d' ' -f19 /proc/self/stat")
+            .unwrap();
+        let niced = |nice, expected| {
+            passing("nice", "sh nice.sh")
+                .nice(nice)
+                .expected_stdout(expected)
+        };
+
+        assert!(run_one(&dir, niced(10, "10\n")).passed());
+        // clamped to the lowest priority
+        assert!(run_one(&dir, niced(100, "19\n")).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn require_elf_rejects_other_executables() {
+        let dir = tests_dir();
+        let script = dir.join("prog");
+        std::fs::write(&script, "#!/bin/sh\necho hi").unwrap();
+        let mode = std::os::unix::fs::PermissionsExt::from_mode(0o755);
+        std::fs::set_permissions(&script, mode).unwrap();
+
+        assert!(run_one(&dir, passing("prog", "./prog")).passed());
+        let elf_only = passing("prog", "./prog").require_elf(true);
+        assert!(!run_one(&dir, elf_only).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fixture_exit_files_set_the_expected_exit_code() {
+        let dir = tests_dir();
+        std::fs::write(
+            dir.join("exits.sh"),
+            "read code\necho $code\nexit $code",
+        )
+        .unwrap();
+        let fixtures = dir.join("fixtures");
+        std::fs::create_dir(&fixtures).unwrap();
+        // the code read, and the one the .exit file expects if any
+        for (case, code, exit) in [
+            ("a-zero", "0", None),
+            ("b-two", "2", Some("2\n")),
+            ("c-unexpected", "2", None),
+            ("d-missed", "0", Some("2")),
+        ] {
+            let input = fixtures.join(case).with_extension("in");
+            std::fs::write(&input, code).unwrap();
+            std::fs::write(input.with_extension("out"), format!("{}\n", code))
+                .unwrap();
+            if let Some(exit) = exit {
+                std::fs::write(input.with_extension("exit"), exit).unwrap();
+            }
+        }
+
+        let mut exits = manager(&dir);
+        exits.register_template(passing("exits", "sh exits.sh").build());
+        exits.instantiate_from_fixtures("exits", &fixtures).unwrap();
+        let passed: Vec<bool> =
+            exits.run_tests().iter().map(|(_, o)| o.passed()).collect();
+        assert_eq!(passed, [true, true, false, false]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn templates_needing_port_are_listed() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        for template in [
+            template("plain", "echo hi"),
+            template("argument", "./server {}"),
+            template("client", "./server").communicate(true),
+            template("piped", "./client").pipe_from("./server {}"),
+            template("named", "./server {server_port}"),
+        ] {
+            manager.register_template(template.build());
+        }
+
+        let mut needing_port = manager.templates_needing_port();
+        needing_port.sort();
+        assert_eq!(needing_port, ["argument", "client", "piped"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_pty_makes_stdout_a_terminal() {
+        let dir = tests_dir();
+        let script = "if [ -t 1 ]; then echo tty; else echo pipe; fi";
+        std::fs::write(dir.join("isatty.sh"), script).unwrap();
+        let isatty = || passing("isatty", "sh isatty.sh");
+
+        assert!(run_one(&dir, isatty().expected_stdout("pipe\n")).passed());
+        let pty = isatty().pty(true).expected_stdout("tty\n");
+        assert!(run_one(&dir, pty).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prompts_are_stripped_before_comparison() {
+        let dir = tests_dir();
+        std::fs::write(
+            dir.join("prompts.sh"),
+            r"printf '> answer\n> answer2\n'",
+        )
+        .unwrap();
+        let prompts = || {
+            passing("prompts", "sh prompts.sh")
+                .expected_stdout("answer\nanswer2\n")
+        };
+
+        assert!(!run_one(&dir, prompts()).passed());
+        assert!(run_one(&dir, prompts().strip_prompt("> ")).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_compile_is_redone_with_diagnostics() {
+        let dir = tests_dir();
+        let source = "int main(void) { int unused; return missing; }\n";
+        std::fs::write(dir.join("main.c"), source).unwrap();
+        let mut build = manager(&dir);
+
+        let error =
+            build.compile_with_diagnostics("gcc -std=c11 main.c -o main");
+        let Err(Error::Compile(error)) = error else {
+            panic!("[-] Expected a compile error");
+        };
+        assert!(error.command.ends_with(&DIAGNOSTIC_FLAGS.join(" ")));
+        assert!(error.output.contains("-Wunused-variable"));
+        let primary = dir.join("compilation_output.txt");
+        let primary = std::fs::read_to_string(primary).unwrap();
+        assert!(primary.contains("missing"));
+        assert!(!primary.contains("-Wunused-variable"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_diagnostic_recompile_past_the_timeout_is_a_timeout() {
+        let dir = tests_dir();
+        // fails at once, but hangs given the diagnostic flags
+        let script = "case \"$*\" in *-Wall*) sleep 30;; esac\n\
+                      echo 'error: bad' >&2";
+        std::fs::write(dir.join("build.sh"), script).unwrap();
+        let timeout = Duration::from_millis(500);
+        let mut build = manager(&dir).with_compile_timeout(timeout);
+
+        let error = build.compile_with_diagnostics("sh build.sh x -o main");
+        assert!(matches!(error, Err(Error::Timeout(t)) if t == timeout));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stderr_is_compared_against_a_golden_file() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("usage.sh"), "echo 'usage: prog <file>' >&2")
+            .unwrap();
+        std::fs::write(dir.join("usage.txt"), "usage: prog <file>\n").unwrap();
+        std::fs::write(dir.join("other.txt"), "usage: prog\n").unwrap();
+        let usage = |golden: &str| {
+            passing("usage", "sh usage.sh").expected_stderr_file(golden.into())
+        };
+
+        assert!(run_one(&dir, usage("usage.txt")).passed());
+        assert!(!run_one(&dir, usage("other.txt")).passed());
+        assert!(!run_one(&dir, usage("missing.txt")).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_recorded_run_replays_its_outcomes_and_output() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        for template in [
+            template("echo", "echo hi"),
+            // not valid UTF-8, archived byte for byte
+            template("binary", "printf \\377out"),
+        ] {
+            let name = manager.register_template(template.build());
+            manager.instantiate_test(&name, None);
+        }
+
+        let archive_path = dir.join("run.json");
+        let results = manager.record_run(&archive_path).unwrap();
+        let archive = crate::report::archive::replay(&archive_path).unwrap();
+        let json = |results: &[(String, TestOutcome)]| {
+            serde_json::to_value(results).unwrap()
+        };
+        assert_eq!(json(&archive.results()), json(&results));
+
+        let binary = &archive.tests[1];
+        assert_eq!(binary.cmd_args, ["printf", "\\377out"]);
+        assert_eq!(binary.stdout, b"\xffout");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_embed_an_excerpt_of_the_output() {
+        let dir = tests_dir();
+        let outcome = run_one(&dir, passing("seq", "seq 100000"));
+        assert_eq!(outcome.stdout_excerpt, None);
+
+        let excerpted = passing("seq", "seq 100000").report_output_lines(4);
+        let outcome = run_one(&dir, excerpted);
+        assert_eq!(
+            outcome.stdout_excerpt.unwrap(),
+            "1\n2\n... 99996 lines elided ...\n99999\n100000\n"
+        );
+        assert_eq!(outcome.stderr_excerpt.unwrap(), "");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn args_expand_the_test_environment() {
+        let template =
+            template("env", "./prog $INPUT ${INPUT}.bak $TESTS_LIB_UNSET")
+                .env("INPUT", "data.txt")
+                .build();
+        let test = template.instantiate(None);
+        assert_eq!(
+            test.cmd_args(),
+            ["./prog", "data.txt", "data.txt.bak", "$TESTS_LIB_UNSET"]
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Unresolved environment variable $TESTS_LIB_UNSET"
+    )]
+    fn strict_env_rejects_unknown_variables() {
+        let template =
+            template("env", "./prog $TESTS_LIB_UNSET").strict_env(true);
+        template.build().instantiate(None);
+    }
+
+    #[test]
+    fn a_parser_reading_past_eof_is_blocked_on_input() {
+        let dir = tests_dir();
+        let hangs = "while :; do read line || sleep 0.1; done";
+        std::fs::write(dir.join("hangs.sh"), hangs).unwrap();
+        std::fs::write(dir.join("slow.sh"), "read line\nsleep 1").unwrap();
+        let parser = |name: &str, grace| {
+            passing(name, &format!("sh {}.sh", name))
+                .timeout(20)
+                .stdin_script(StdinScript::new().line(Duration::ZERO, "1 + 2"))
+                .detect_blocked_input(grace)
+        };
+
+        let outcome =
+            run_one(&dir, parser("hangs", Duration::from_millis(500)));
+        assert!(!outcome.passed());
+        assert!(outcome.duration < Duration::from_secs(10));
+        assert!(run_one(&dir, parser("slow", Duration::from_secs(3))).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn soft_assertions_become_the_reason() {
+        let dir = tests_dir();
+        let checked = template("checked", "echo 4").agent(Box::new(|| {
+            Box::new(SoftAssertAgent::new(|output, check| {
+                check.check(output.stdout_str() == "3\n", "prints 3");
+                check.check(output.stderr.len() == 1, "one stderr byte");
+                check.fail("always");
+            }))
+        }));
+        let outcome = run_one(&dir, checked);
+        assert_eq!(
+            outcome.reason.unwrap(),
+            "prints 3; one stderr byte; always"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stdout_must_fall_within_the_expected_bytes() {
+        let dir = tests_dir();
+        let sized = |args: &str, min, max| {
+            passing("sized", args).expect_stdout_bytes(min, max)
+        };
+        assert!(!run_one(&dir, sized("true", Some(1), None)).passed());
+        assert!(run_one(&dir, sized("echo hi", Some(1), Some(3))).passed());
+        assert!(!run_one(&dir, sized("echo hello", None, Some(3))).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn discarded_output_is_drained_and_counted() {
+        let dir = tests_dir();
+        let verbose = template("verbose", "head -c 50000000 /dev/zero")
+            .discard_stdout(true)
+            .agent(Box::new(|| {
+                Box::new(SoftAssertAgent::new(|output, check| {
+                    check.check(output.stdout.is_empty(), "stdout was kept");
+                }))
+            }));
+        let outcome = run_one(&dir, verbose);
+        assert!(outcome.passed());
+        assert_eq!(outcome.stdout_bytes, 50_000_000);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tests_expose_their_settings() {
+        let server = template("server", "./server {}")
+            .timeout(7)
+            .communicate(true)
+            .operation_timeout(2)
+            .build()
+            .instantiate(Some(8080));
+        assert_eq!(server.name(), "server");
+        assert_eq!(server.cmd_args(), ["./server", "8080"]);
+        assert_eq!(server.port(), Some(8080));
+        assert_eq!(server.timeout(), 7);
+        assert!(server.requires_communicator());
+
+        let plain = template("plain", "true").build().instantiate(None);
+        assert_eq!(plain.port(), None);
+        assert!(!plain.requires_communicator());
+    }
+
+    #[test]
+    fn a_stdin_script_drives_an_interactive_session() {
+        let dir = tests_dir();
+        let session = "read a\necho got $a\nread b\necho got $b\ncat";
+        std::fs::write(dir.join("session.sh"), session).unwrap();
+        let pause = Duration::from_millis(300);
+        let script = StdinScript::new()
+            .line(Duration::ZERO, "first")
+            .line(pause, "second")
+            .send(pause, b"rest".to_vec())
+            .close_after(pause);
+        let interactive = passing("session", "sh session.sh")
+            .timeout(10)
+            .stdin_script(script)
+            .expected_stdout("got first\ngot second\nrest");
+
+        let outcome = run_one(&dir, interactive);
+        assert!(outcome.passed());
+        assert!(outcome.duration >= pause * 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_socket_held_until_the_timeout_is_a_leak() {
+        let dir = tests_dir();
+        let listens = "import socket, time\n\
+            server = socket.socket()\n\
+            server.bind(('127.0.0.1', 0))\n\
+            server.listen()\n\
+            time.sleep(30)\n";
+        std::fs::write(dir.join("listens.py"), listens).unwrap();
+        // long enough for python to start and bind under a loaded machine
+        let leaky = passing("listens", "python3 listens.py")
+            .timeout(3)
+            .detect_socket_leaks(true);
+
+        let outcome = run_one(&dir, leaky);
+        assert!(outcome.socket_leak_detected);
+        assert!(!outcome.passed());
+        let quiet = passing("quiet", "true").detect_socket_leaks(true);
+        assert!(!run_one(&dir, quiet).socket_leak_detected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // running, not a zombie waiting for its parent
+    fn alive(pid: i32) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(
+            |stat| !stat.rsplit(')').next().unwrap().starts_with(" Z"),
+        )
+    }
+
+    #[test]
+    fn a_process_group_leaves_no_forked_children() {
+        let dir = tests_dir();
+        let forks = "sleep 30 > /dev/null 2>&1 &\necho $! > child.pid\nwait";
+        std::fs::write(dir.join("forks.sh"), forks).unwrap();
+        let forks = || passing("forks", "sh forks.sh").timeout(1);
+        let child = || {
+            let pid = std::fs::read_to_string(dir.join("child.pid")).unwrap();
+            pid.trim().parse::<i32>().unwrap()
+        };
+
+        run_one(&dir, forks().process_group(true));
+        assert!(!alive(child()));
+
+        run_one(&dir, forks());
+        let orphan = child();
+        assert!(alive(orphan));
+        unsafe { libc::kill(orphan, libc::SIGKILL) };
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_taken_template_name_gets_a_suffix() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        let names: Vec<String> = ["one", "two", "three"]
+            .iter()
+            .map(|word| {
+                let echo = passing("echo", &format!("echo {}", word))
+                    .expected_stdout(format!("{}\n", word));
+                manager.register_template(echo.build())
+            })
+            .collect();
+        assert_eq!(names, ["echo", "echo#2", "echo#3"]);
+
+        for name in &names {
+            manager.instantiate_test(name, None);
+        }
+        let results = manager.run_tests();
+        assert!(results.iter().all(|(_, outcome)| outcome.passed()));
+        let ran: Vec<&str> =
+            results.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(ran, names);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn instantiate_all_allocates_ports_where_needed() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        for template in [
+            template("first", "true"),
+            template("server", "./server {}"),
+            template("last", "true"),
+        ] {
+            manager.register_template(template.build());
+        }
+
+        let mut asked = Vec::new();
+        let names = manager.instantiate_all(|name| {
+            asked.push(name.to_string());
+            9000
+        });
+        assert_eq!(names, ["first", "server", "last"]);
+        assert_eq!(asked, ["server"]);
+        assert_eq!(manager.active_tests.len(), 3);
+        let server = &manager.active_tests["server"];
+        assert_eq!(server.cmd_args(), ["./server", "9000"]);
+        assert_eq!(manager.active_tests["first"].port(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_done_sentinel_ends_the_test_early() {
+        let dir = tests_dir();
+        let serves = "echo 42\necho READY\nwhile :; do sleep 0.1; done";
+        std::fs::write(dir.join("serves.sh"), serves).unwrap();
+        let server = || {
+            passing("serves", "sh serves.sh")
+                .timeout(20)
+                .process_group(true)
+                .done_on_stdout_contains("READY")
+                .agent(Box::new(|| {
+                    Box::new(SoftAssertAgent::new(|output, check| {
+                        let stdout = output.stdout_str();
+                        let answered = stdout.starts_with("42\n");
+                        check.check(answered, "prints 42 first");
+                    }))
+                }))
+        };
+
+        let outcome = run_one(&dir, server());
+        assert!(outcome.passed());
+        assert!(!outcome.truncated_by_timeout);
+        assert!(outcome.duration < Duration::from_secs(10));
+        // the process never exited on its own
+        assert_eq!(outcome.exit_code, None);
+        assert!(!run_one(&dir, server().expected_exit_code(0)).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn inline_expected_output_is_compared_after_normalizing() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("answers.sh"), "echo 42\necho 'no input' >&2")
+            .unwrap();
+        let answers = || passing("answers", "sh answers.sh");
+
+        assert!(run_one(&dir, answers().expected_stdout(b"42\n")).passed());
+        assert!(!run_one(&dir, answers().expected_stdout(b"43\n")).passed());
+        let stderr = answers().expected_stderr(b"no input\n".to_vec());
+        assert!(run_one(&dir, stderr).passed());
+        let uppercased = answers()
+            .normalizer(|bytes| bytes.to_ascii_uppercase())
+            .expected_stderr(b"NO INPUT\n".to_vec());
+        assert!(run_one(&dir, uppercased).passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn leaked_children_are_found_running_and_as_zombies() {
+        let dir = tests_dir();
+        let manager = manager(&dir);
+        // other tests run their own processes alongside, only these count
+        let find = |pid: u32| {
+            manager
+                .assert_no_leaked_children()
+                .into_iter()
+                .find(|child| child.pid == pid as i32)
+        };
+
+        let mut running = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let child = find(running.id()).unwrap();
+        assert_eq!((child.command.as_str(), child.zombie), ("sleep", false));
+
+        running.kill().unwrap();
+        // killed but not yet waited for, once the signal has landed
+        let zombie = (0..100).any(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            find(running.id()).unwrap().zombie
+        });
+        assert!(zombie);
+        running.wait().unwrap();
+        assert!(find(running.id()).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_smoke_tests_skip_the_rest() {
+        let dir = tests_dir();
+        let suite = |smoke_args: &str| {
+            let mut manager = manager(&dir);
+            for template in [
+                template("full", "touch full.ran"),
+                template("smoke", smoke_args).smoke(true),
+            ] {
+                let name = manager.register_template(template.build());
+                manager.instantiate_test(&name, None);
+            }
+            manager
+                .run_smoke_then_full()
+                .into_iter()
+                .map(|(name, outcome)| (name, outcome.status))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            suite("false"),
+            [
+                ("smoke".to_string(), TestStatus::Failed),
+                ("full".to_string(), TestStatus::Skipped),
+            ]
+        );
+        assert!(!dir.join("full.ran").exists());
+        assert_eq!(
+            suite("true"),
+            [
+                ("smoke".to_string(), TestStatus::Passed),
+                ("full".to_string(), TestStatus::Passed),
+            ]
+        );
+        assert!(dir.join("full.ran").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_skipped_smoke_test_skips_the_rest() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        // its dependency is never instantiated
+        let smoke = template("smoke", "true").smoke(true).depends_on("setup");
+        manager.register_template(smoke.build());
+        manager.register_template(template("full", "true").build());
+        manager.instantiate_test("smoke", None);
+        manager.instantiate_test("full", None);
+
+        let results = manager.run_smoke_then_full();
+        assert_eq!(names(&results), ["smoke", "full"]);
+        assert_eq!(results[0].1.status, TestStatus::Skipped);
+        assert_eq!(results[1].1.reason.as_deref(), Some("smoke tests failed"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compiles_are_bounded_by_default() {
+        let dir = tests_dir();
+        assert_eq!(manager(&dir).compile_timeout, DEFAULT_COMPILE_TIMEOUT);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_and_run_reports_a_compile_timeout() {
+        let dir = tests_dir();
+        let timeout = Duration::from_millis(500);
+        let mut manager = manager(&dir).with_compile_timeout(timeout);
+        manager.register_template(template("echo", "echo hi").build());
+        manager.instantiate_test("echo", None);
+
+        let result = manager.build_and_run("sleep 30 # -o prog");
+        assert!(matches!(result, Err(Error::Timeout(t)) if t == timeout));
+        let report = manager.compile_report.as_ref().unwrap();
+        assert!(report.timed_out() && report.failed());
+        assert_eq!(manager.compile_assignment("sleep 30 # -o prog"), "timeout");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn suites_run_and_aggregate_separately() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(template("a", "true").suite("part1").build());
+        manager
+            .register_template(template("b", "false").suite("part1").build());
+        manager.register_template(template("c", "true").suite("part2").build());
+        for name in ["a", "b", "c"] {
+            manager.instantiate_test(name, None);
+        }
+
+        let part2 = manager.run_suite("part2");
+        assert_eq!(part2.len(), 1);
+        assert_eq!(part2[0].0, "c");
+
+        let results = manager.run_tests();
+        let suites = manager.results_by_suite(&results);
+        let names: Vec<&str> = suites.keys().map(|s| s.as_str()).collect();
+        assert_eq!(names, ["part1", "part2"]);
+        assert_eq!(suites["part1"].total(), 2);
+        assert_eq!(suites["part1"].passed(), 1);
+        assert_eq!(suites["part1"].failed(), 1);
+        assert_eq!(suites["part2"].passed(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn names(results: &[(String, TestOutcome)]) -> Vec<&str> {
+        results.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn a_failed_dependency_skips_its_dependents() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        // instantiated before its dependency, still run after it
+        manager
+            .register_template(template("b", "true").depends_on("a").build());
+        manager.register_template(template("a", "false").build());
+        manager.instantiate_test("b", None);
+        manager.instantiate_test("a", None);
+
+        for results in [manager.run_tests(), manager.run_suite("default")] {
+            assert_eq!(names(&results), ["a", "b"]);
+            assert_eq!(results[0].1.status, TestStatus::Failed);
+            assert_eq!(results[1].1.status, TestStatus::Skipped);
+            assert_eq!(
+                results[1].1.reason.as_deref(),
+                Some("dependency failed")
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn run_single_runs_the_dependencies_first() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("b", "test -e a.ran").depends_on("a").build(),
+        );
+        manager.register_template(template("a", "touch a.ran").build());
+        manager
+            .register_template(template("c", "true").depends_on("d").build());
+        manager.register_template(template("d", "false").build());
+        for name in ["a", "b", "c", "d"] {
+            manager.instantiate_test(name, None);
+        }
+
+        assert!(manager.run_single("b").unwrap().passed());
+        let skipped = manager.run_single("c").unwrap();
+        assert_eq!(skipped.status, TestStatus::Skipped);
+        assert_eq!(skipped.reason.as_deref(), Some("dependency failed"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Dependency cycle: a -> b -> a")]
+    fn a_dependency_cycle_panics() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager
+            .register_template(template("a", "true").depends_on("b").build());
+        manager
+            .register_template(template("b", "true").depends_on("a").build());
+        manager.instantiate_test("a", None);
+        manager.instantiate_test("b", None);
+        manager.run_tests();
+    }
+
+    #[test]
+    fn rerun_failed_reruns_dependencies_first() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager
+            .register_template(template("b", "true").depends_on("a").build());
+        manager.register_template(template("a", "true").build());
+        manager.register_template(template("c", "true").build());
+        for name in ["b", "a", "c"] {
+            manager.instantiate_test(name, None);
+        }
+
+        let mut previous = manager.run_tests();
+        assert_eq!(names(&previous), ["a", "b", "c"]);
+        for (_, outcome) in previous.iter_mut().take(2) {
+            outcome.status = TestStatus::Failed;
+        }
+        previous.swap(0, 1);
+
+        let rerun = manager.rerun_failed(&previous);
+        assert_eq!(names(&rerun), ["a", "b"]);
+        assert!(rerun.iter().all(|(_, outcome)| outcome.passed()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rerun_failed_reruns_exactly_the_failures() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        let tests =
+            [("a", "true"), ("b", "false"), ("c", "true"), ("d", "false")];
+        for (name, args) in tests {
+            manager.register_template(template(name, args).build());
+            manager.instantiate_test(name, None);
+        }
+
+        let previous = manager.run_tests();
+        let rerun = manager.rerun_failed(&previous);
+        assert_eq!(names(&rerun), ["b", "d"]);
+        assert!(rerun.iter().all(|(_, o)| o.status == TestStatus::Failed));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // passes on exit code 0 and records the directory it validated in
+    struct DirAgent;
+
+    #[async_trait]
+    impl TestAgent for DirAgent {
+        async fn validate_with_context(
+            &self,
+            context: &TestContext,
+            _args: &Vec<String>,
+            _communicate_output: Option<CommunicateOutput>,
+            output: ProcessOutput,
+            cwd: &std::path::PathBuf,
+        ) -> bool {
+            context.record("dir", cwd.display());
+            output.status.is_ok_and(|status| status.success())
+        }
+    }
+
+    // records how many clients it was given
+    struct ClientsAgent;
+
+    #[async_trait]
+    impl TestAgent for ClientsAgent {
+        async fn communicate(
+            &self,
+            _read_timeout: u64,
+            _port: &str,
+            _process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            CommunicateOutput::default()
+        }
+
+        async fn validate_clients_with_context(
+            &self,
+            context: &TestContext,
+            _args: &Vec<String>,
+            communicate_outputs: Vec<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            context.record("clients", communicate_outputs.len());
+            true
+        }
+    }
+
+    // retries a server that never answers until its timeout runs out
+    struct PatientAgent;
+
+    #[async_trait]
+    impl TestAgent for PatientAgent {
+        async fn communicate(
+            &self,
+            read_timeout: u64,
+            _port: &str,
+            _process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            tokio::time::sleep(Duration::from_secs(read_timeout)).await;
+            CommunicateOutput::default()
+        }
+
+        async fn validate(
+            &self,
+            _args: &Vec<String>,
+            communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            communicate_output.is_some_and(|output| output.error.is_none())
+        }
+    }
+
+    #[test]
+    fn communicate_stops_once_the_server_exits() {
+        let dir = tests_dir();
+        let exits = template("exits", "true")
+            .communicate(true)
+            .operation_timeout(30)
+            .agent(Box::new(|| Box::new(PatientAgent)));
+        let mut manager = manager(&dir);
+        manager.register_template(exits.build());
+        manager.instantiate_test("exits", Some(1));
+
+        let outcome = manager.run_tests().remove(0).1;
+        assert!(!outcome.passed());
+        assert!(outcome.duration < Duration::from_secs(5));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // echoes one message per connection, exiting after `argv[2]` of them
+    const ECHO_SERVER: &str = "\
+import socket, sys, threading
+server = socket.socket()
+server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+server.bind(('127.0.0.1', int(sys.argv[1])))
+server.listen(16)
+def echo(client):
+    client.sendall(client.recv(1024))
+    client.close()
+for _ in range(int(sys.argv[2])):
+    client, _ = server.accept()
+    threading.Thread(target=echo, args=(client,)).start()
+";
+
+    fn free_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    struct EchoAgent;
+
+    #[async_trait]
+    impl TestAgent for EchoAgent {
+        async fn communicate(
+            &self,
+            read_timeout: u64,
+            port: &str,
+            _process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            let timeout = Duration::from_secs(read_timeout);
+            let exchange = async {
+                let port = port.parse().unwrap();
+                let mut stream =
+                    crate::wait_for_port("127.0.0.1", port, timeout).await?;
+                stream.write_all(b"ping").await?;
+                let mut echoed = Vec::new();
+                tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut echoed)
+                    .await?;
+                Ok::<_, std::io::Error>(echoed)
+            };
+            match exchange.await {
+                Ok(echoed) => CommunicateOutput {
+                    output: vec![echoed],
+                    ..Default::default()
+                },
+                Err(e) => CommunicateOutput {
+                    error: Some(e),
+                    ..Default::default()
+                },
+            }
+        }
+
+        async fn validate_clients(
+            &self,
+            _args: &Vec<String>,
+            communicate_outputs: Vec<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            communicate_outputs.len() == 10
+                && communicate_outputs
+                    .iter()
+                    .all(|client| client.output == [b"ping"])
+        }
+    }
+
+    #[test]
+    fn concurrent_clients_each_get_an_output() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("echo.py"), ECHO_SERVER).unwrap();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("echo", "python3 echo.py {} 10")
+                .communicate(true)
+                .operation_timeout(5)
+                .clients(10)
+                .agent(Box::new(|| Box::new(EchoAgent)))
+                .build(),
+        );
+        manager.instantiate_test("echo", Some(free_port()));
+
+        assert!(manager.run_tests()[0].1.passed());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // validates each client on its own, counting the calls
+    struct PerClientAgent(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl TestAgent for PerClientAgent {
+        async fn communicate(
+            &self,
+            read_timeout: u64,
+            port: &str,
+            process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            EchoAgent.communicate(read_timeout, port, process_id).await
+        }
+
+        async fn validate(
+            &self,
+            _args: &Vec<String>,
+            communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            communicate_output.is_some_and(|client| client.output == [b"ping"])
+        }
+    }
+
+    #[test]
+    fn clients_are_validated_one_by_one_by_default() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("echo.py"), ECHO_SERVER).unwrap();
+        let validated = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&validated);
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("echo", "python3 echo.py {} 3")
+                .communicate(true)
+                .operation_timeout(5)
+                .clients(3)
+                .agent(Box::new(move || {
+                    Box::new(PerClientAgent(Arc::clone(&counter)))
+                }))
+                .build(),
+        );
+        manager.instantiate_test("echo", Some(free_port()));
+
+        assert!(manager.run_tests()[0].1.passed());
+        assert_eq!(validated.load(Ordering::Relaxed), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    // keeps its connection open past communicate, closing it in finalize
+    struct ConnectionAgent {
+        stream: Mutex<Option<tokio::net::TcpStream>>,
+        calls: Arc<std::sync::Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl TestAgent for ConnectionAgent {
+        async fn communicate(
+            &self,
+            read_timeout: u64,
+            port: &str,
+            _process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            let timeout = Duration::from_secs(read_timeout);
+            let port = port.parse().unwrap();
+            let mut stream = crate::wait_for_port("127.0.0.1", port, timeout)
+                .await
+                .unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            *self.stream.lock().await = Some(stream);
+            self.calls.lock().unwrap().push("communicate");
+            CommunicateOutput::default()
+        }
+
+        async fn validate(
+            &self,
+            _args: &Vec<String>,
+            _communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            let open = self.stream.lock().await.is_some();
+            self.calls.lock().unwrap().push("validate");
+            open
+        }
+
+        async fn finalize(&self) {
+            let stream = self.stream.lock().await.take();
+            stream.unwrap().shutdown().await.unwrap();
+            self.calls.lock().unwrap().push("finalize");
+        }
+    }
+
+    #[test]
+    fn finalize_closes_what_communicate_opened() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("echo.py"), ECHO_SERVER).unwrap();
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let agent_calls = Arc::clone(&calls);
+        let connected = template("connected", "python3 echo.py {} 1")
+            .communicate(true)
+            .operation_timeout(5)
+            .agent(Box::new(move || {
+                Box::new(ConnectionAgent {
+                    stream: Mutex::new(None),
+                    calls: Arc::clone(&agent_calls),
+                })
+            }));
+
+        let mut manager = manager(&dir);
+        let name = manager.register_template(connected.build());
+        manager.instantiate_test(&name, Some(free_port()));
+        assert!(manager.run_tests()[0].1.passed());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["communicate", "validate", "finalize"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn each_phase_of_a_run_is_timed() {
+        let dir = tests_dir();
+        std::fs::write(dir.join("echo.py"), ECHO_SERVER).unwrap();
+        std::fs::write(dir.join("main.c"), "int main(void) { return 0; }\n")
+            .unwrap();
+        let mut manager = manager(&dir);
+        assert_eq!(
+            manager.compile_assignment_result("gcc -Wall main.c -o main"),
+            CompileResult::Success
+        );
+        manager.register_template(
+            template("echo", "python3 echo.py {} 1")
+                .communicate(true)
+                .operation_timeout(5)
+                .agent(Box::new(|| {
+                    Box::new(ConnectionAgent {
+                        stream: Mutex::new(None),
+                        calls: Default::default(),
+                    })
+                }))
+                .build(),
+        );
+        manager.instantiate_test("echo", Some(free_port()));
+
+        let outcome = manager.run_tests().remove(0).1;
+        assert!(outcome.passed());
+        let timings = outcome.timings;
+        assert!(timings
+            .compile
+            .is_some_and(|compile| compile > Duration::ZERO));
+        assert!(timings.spawn > Duration::ZERO);
+        assert!(timings.ready >= timings.spawn);
+        assert!(timings.communicate > Duration::ZERO);
+        assert!(timings.process >= timings.communicate);
+        assert!(timings.validate <= outcome.duration);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn every_path_validates_with_the_context() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            TestTemplateBuilder::new("artifact")
+                .artifact_only(true)
+                .agent(Box::new(|| Box::new(DirAgent)))
+                .build(),
+        );
+        manager.register_template(
+            template("clients", "true")
+                .communicate(true)
+                .operation_timeout(1)
+                .clients(2)
+                .agent(Box::new(|| Box::new(ClientsAgent)))
+                .build(),
+        );
+        manager.instantiate_test("artifact", None);
+        manager.instantiate_test("clients", Some(1));
+
+        let results = manager.run_tests();
+        assert!(results.iter().all(|(_, outcome)| outcome.passed()));
+        let dir_recorded = results[0].1.metadata.get("dir");
+        assert_eq!(dir_recorded, Some(&dir.display().to_string()));
+        assert_eq!(results[1].1.metadata.get("clients"), Some(&2.to_string()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn run_batch_grades_submissions_independently() {
+        let root = tests_dir();
+        let submissions: Vec<PathBuf> = [("alice", 0), ("bob", 3)]
+            .iter()
+            .map(|(name, code)| {
+                let dir = root.join(name);
+                std::fs::create_dir_all(&dir).unwrap();
+                let script = format!("echo {}; exit {}", name, code);
+                std::fs::write(dir.join("run.sh"), script).unwrap();
+                dir
+            })
+            .collect();
+        let results_dir = root.join("results");
+
+        let mut manager = manager(&root)
+            .with_results_dir(&results_dir)
+            .with_batch_concurrency(2);
+        manager.register_template(
+            TestTemplateBuilder::new("run")
+                .args_template("sh run.sh")
+                .log_output(true)
+                .agent(Box::new(|| Box::new(DirAgent)))
+                .build(),
+        );
+
+        let results = manager.run_batch(&submissions);
+        assert_eq!(results.len(), 2);
+        for (dir, passed) in submissions.iter().zip([true, false]) {
+            let outcomes = &results[dir];
+            assert_eq!(names(outcomes), ["run"]);
+            assert_eq!(outcomes[0].1.passed(), passed);
+            let recorded = outcomes[0].1.metadata.get("dir");
+            assert_eq!(recorded, Some(&dir.display().to_string()));
+
+            let name = dir.file_name().unwrap();
+            let log = results_dir.join(name).join("output - run.txt");
+            let log = std::fs::read_to_string(log).unwrap();
+            assert_eq!(log.trim(), name.to_str().unwrap());
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn submissions(root: &Path, script: &str) -> Vec<PathBuf> {
+        ["alice", "bob"]
+            .iter()
+            .map(|name| {
+                let dir = root.join(name);
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join("run.sh"), script).unwrap();
+                dir
+            })
+            .collect()
+    }
+
+    #[test]
+    fn run_batch_fails_every_test_of_a_failed_compile() {
+        let root = tests_dir();
+        let submissions = submissions(&root, "exit 0");
+
+        let mut manager = manager(&root)
+            .with_compile_command("gcc -Wall missing.c -o main")
+            .with_batch_concurrency(2);
+        manager.register_template(template("run", "sh run.sh").build());
+
+        let results = manager.run_batch(&submissions);
+        for dir in &submissions {
+            let outcome = &results[dir][0].1;
+            assert_eq!(outcome.status, TestStatus::Failed);
+            assert_eq!(outcome.reason.as_deref(), Some("compilation failed"));
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_batch_errors_a_panicking_test() {
+        let root = tests_dir();
+        let submissions = submissions(&root, "exit 0");
+
+        let mut manager = manager(&root);
+        manager.register_template(
+            template("run", "sh run.sh")
+                .agent(Box::new(|| {
+                    Box::new(SoftAssertAgent::new(|_, _| panic!("broken")))
+                }))
+                .build(),
+        );
+
+        let results = manager.run_batch(&submissions);
+        for dir in &submissions {
+            let outcome = &results[dir][0].1;
+            assert_eq!(outcome.status, TestStatus::Errored);
+            let reason = format!("test aborted in {:?}", dir);
+            assert_eq!(outcome.reason, Some(reason));
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_batch_skips_templates_without_a_port() {
+        let root = tests_dir();
+        let submissions = submissions(&root, "exit 0");
+
+        let mut manager = manager(&root);
+        manager.register_template(template("served", "sh run.sh {}").build());
+        manager.register_template(template("run", "sh run.sh").build());
+
+        let results = manager.run_batch(&submissions);
+        for dir in &submissions {
+            let outcomes = &results[dir];
+            assert_eq!(names(outcomes), ["served", "run"]);
+            assert_eq!(outcomes[0].1.status, TestStatus::Skipped);
+            assert_eq!(outcomes[0].1.reason.as_deref(), Some("no port"));
+            assert_eq!(outcomes[1].1.status, TestStatus::Passed);
+        }
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_batch_runs_port_tests_one_submission_at_a_time() {
+        let root = tests_dir();
+        let script = "echo start >> ../runs; sleep 0.3; echo end >> ../runs";
+        let submissions = submissions(&root, script);
+
+        let mut manager = manager(&root).with_batch_concurrency(2);
+        manager.register_template(template("served", "sh run.sh {}").build());
+        manager.instantiate_test("served", Some(free_port()));
+
+        let results = manager.run_batch(&submissions);
+        assert!(results.values().all(|outcomes| outcomes[0].1.passed()));
+        let runs = std::fs::read_to_string(root.join("runs")).unwrap();
+        assert_eq!(runs, "start\nend\nstart\nend\n");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn pipelines_apply_env_and_stdin() {
+        let dir = tests_dir();
+        let fixtures = dir.join("fixtures");
+        std::fs::create_dir(&fixtures).unwrap();
+        std::fs::write(fixtures.join("shout.in"), "abc\n").unwrap();
+        std::fs::write(fixtures.join("shout.out"), "ABC\n").unwrap();
+
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("env", "printenv FOO")
+                .env("FOO", "bar")
+                .pipe_into("cat")
+                .expected_stdout("bar\n")
+                .build(),
+        );
+        manager.register_template(
+            template("upper", "tr a-z A-Z").pipe_into("cat").build(),
+        );
+        manager.instantiate_test("env", None);
+        manager
+            .instantiate_from_fixtures("upper", &fixtures)
+            .unwrap();
+
+        let results = manager.run_tests();
+        assert!(results.iter().all(|(_, outcome)| outcome.passed()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_pipeline_stage_that_cannot_start_errors_the_test() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("broken", "echo hi").pipe_into("./no-such-prog").build(),
+        );
+        manager.instantiate_test("broken", None);
+
+        let outcome = manager.run_tests().remove(0).1;
+        assert_eq!(outcome.status, TestStatus::Errored);
+        let reason = outcome.reason.unwrap();
+        let missing = "./no-such-prog could not run";
+        assert!(reason.starts_with(missing), "{}", reason);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Args template is required")]
+    fn a_blank_args_template_is_rejected() {
+        template("blank", "  ").build();
+    }
+
+    #[test]
+    fn a_blank_args_template_is_allowed_when_artifact_only() {
+        let artifact = template("artifact", "").artifact_only(true).build();
+        assert!(artifact.instantiate(None).cmd_args().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "A pipeline stage cannot be empty")]
+    fn an_empty_pipeline_stage_is_rejected() {
+        template("empty", "cat").pipe_into(" ").build();
+    }
+
+    #[test]
+    #[should_panic(expected = "A pipeline test cannot use a pty")]
+    fn unsupported_pipeline_options_are_rejected() {
+        template("pty", "cat").pipe_into("cat").pty(true).build();
+    }
+
+    #[test]
+    fn fixtures_become_tests_with_their_own_logs() {
+        let dir = tests_dir();
+        let fixtures = dir.join("fixtures");
+        std::fs::create_dir(&fixtures).unwrap();
+        for case in ["case01", "case02", "case03"] {
+            let input = fixtures.join(case).with_extension("in");
+            std::fs::write(&input, case).unwrap();
+            std::fs::write(input.with_extension("out"), case).unwrap();
+        }
+        std::fs::write(fixtures.join("case03.out"), "other").unwrap();
+        std::fs::write(fixtures.join("orphan.in"), "").unwrap();
+
+        let mut cat = manager(&dir);
+        cat.register_template(template("cat", "cat").build());
+        let names = cat.instantiate_from_fixtures("cat", &fixtures).unwrap();
+        assert_eq!(names, ["case01", "case02", "case03"]);
+
+        let results = cat.run_tests();
+        let passed: Vec<bool> =
+            results.iter().map(|(_, o)| o.passed()).collect();
+        assert_eq!(passed, [true, true, false]);
+
+        let mut memcheck = manager(&dir);
+        memcheck.register_template(
+            template("memcheck", "cat").valgrind(true).build(),
+        );
+        memcheck
+            .instantiate_from_fixtures("memcheck", &fixtures)
+            .unwrap();
+        let logs: Vec<_> = memcheck
+            .active_tests
+            .values()
+            .map(|test| test.valgrind_log.clone().unwrap())
+            .collect();
+        assert_eq!(
+            logs,
+            [
+                "valgrind - case01",
+                "valgrind - case02",
+                "valgrind - case03"
+            ]
+            .map(PathBuf::from)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn duration_covers_the_isolated_execution() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        manager.register_template(
+            template("sleeper", "sleep 0.2").isolated(true).build(),
+        );
+        manager.instantiate_test("sleeper", None);
+        let duration = manager.run_tests()[0].1.duration;
+        assert!(duration >= Duration::from_millis(200), "{:?}", duration);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_deadline_covers_the_longer_timeout() {
+        let bounded = template("bounded", "true")
+            .timeout(2)
+            .operation_timeout(3)
+            .build()
+            .instantiate(None);
+        assert_eq!(
+            bounded.deadline(1),
+            Some(Duration::from_secs(4) + RUN_DEADLINE_SLACK)
+        );
+
+        // a process without a timeout is bounded by the operation timeout
+        let operation = template("operation", "true")
+            .timeout(0)
+            .operation_timeout(3)
+            .build()
+            .instantiate(None);
+        assert_eq!(
+            operation.deadline(1),
+            Some(Duration::from_secs(4) + RUN_DEADLINE_SLACK)
+        );
+
+        // and without either it is waited for indefinitely
+        let unbounded =
+            template("unbounded", "true").timeout(0).build().instantiate(None);
+        assert_eq!(unbounded.deadline(1), None);
+    }
+
+    #[test]
+    fn a_run_without_a_timeout_outlasts_the_deadline_slack() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        let slack = RUN_DEADLINE_SLACK.as_secs() + 1;
+        manager.register_template(
+            template("long", &format!("sleep {}", slack)).timeout(0).build(),
+        );
+        manager.instantiate_test("long", None);
+        let outcome = manager.run_tests().remove(0).1;
+        assert_eq!(outcome.status, TestStatus::Passed, "{:?}", outcome.reason);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    struct StuckAgent;
+
+    #[async_trait]
+    impl TestAgent for StuckAgent {
+        async fn communicate(
+            &self,
+            _read_timeout: u64,
+            _port: &str,
+            _process_id: Option<i32>,
+        ) -> CommunicateOutput {
+            std::future::pending().await
+        }
+
+        async fn validate(
+            &self,
+            _args: &Vec<String>,
+            _communicate_output: Option<CommunicateOutput>,
+            _output: ProcessOutput,
+            _cwd: &std::path::PathBuf,
+        ) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn a_deadlocked_run_errors_at_its_deadline() {
+        let dir = tests_dir();
+        // neither the server nor the agent ever gives up on its own
+        let stuck = template("stuck", "sleep 30")
+            .timeout(0)
+            .communicate(true)
+            .operation_timeout(1)
+            .agent(Box::new(|| Box::new(StuckAgent)))
+            .build();
+        let mut manager = manager(&dir);
+        manager.register_template(stuck);
+        manager.instantiate_test("stuck", Some(free_port()));
+
+        let started = std::time::Instant::now();
+        let outcome = manager.run_tests().remove(0).1;
+        assert!(started.elapsed() < Duration::from_secs(15));
+        assert_eq!(outcome.status, TestStatus::Errored);
+        assert_eq!(outcome.reason.as_deref(), Some("deadline exceeded"));
+        assert_eq!(TestManager::stats(&[("stuck".into(), outcome)]).errored, 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn peak_rss_is_sampled_with_or_without_max_rss() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir);
+        // holds 64 MiB for long enough to be sampled
+        let args =
+            "python3 -c b=bytearray(64<<20);__import__('time').sleep(0.3)";
+        manager
+            .register_template(template("big", args).max_rss(1 << 30).build());
+        manager.register_template(template("unbounded", args).build());
+        manager.instantiate_test("big", None);
+        manager.instantiate_test("unbounded", None);
+
+        let results = manager.run_tests();
+        for (name, outcome) in &results {
+            assert!(outcome.passed(), "{}", name);
+            let peak = outcome.peak_rss_bytes.unwrap();
+            assert!((64 << 20..1 << 30).contains(&peak), "peak {}", peak);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exceeding_max_rss_fails_the_test() {
+        let dir = tests_dir();
+        let args =
+            "python3 -c b=bytearray(64<<20);__import__('time').sleep(0.3)";
+        let outcome = run_one(&dir, template("big", args).max_rss(16 << 20));
+        assert_eq!(outcome.status, TestStatus::Failed);
+        assert!(outcome.peak_rss_bytes.unwrap() > 16 << 20);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn catalog_reflects_the_templates() {
+        let dir = tests_dir();
+        let mut manager = manager(&dir).with_default_timeout(7);
+        manager.register_template(
+            template("add", "./calc add")
+                .suite("arith")
+                .description("adds two numbers")
+                .points(2.5)
+                .build(),
+        );
+        manager.register_template(
+            template("slow", "./calc slow")
+                .timeout(30)
+                .xfail(true)
+                .smoke(true)
+                .build(),
+        );
+
+        let catalog = manager.catalog();
+        assert_eq!(catalog.len(), 2);
+        assert_eq!(catalog[0].name, "add");
+        assert_eq!(catalog[0].suite, "arith");
+        assert_eq!(catalog[0].description, "adds two numbers");
+        assert_eq!(catalog[0].points, 2.5);
+        assert_eq!(catalog[0].timeout, 7);
+        assert!(catalog[0].tags.is_empty());
+        assert_eq!(catalog[1].timeout, 30);
+        assert_eq!(catalog[1].tags, ["xfail", "smoke"]);
+        let json = serde_json::to_value(&catalog[1]).unwrap();
+        assert_eq!(json["tags"], serde_json::json!(["xfail", "smoke"]));
+        assert!(manager.active_tests.is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
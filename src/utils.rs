@@ -1,5 +1,8 @@
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub fn dir_has_src_files(path: &PathBuf) -> bool {
     let files = match fs::read_dir(path) {
@@ -7,15 +10,496 @@ pub fn dir_has_src_files(path: &PathBuf) -> bool {
         Err(e) => panic!("[!] Error reading directory: {:?}", e),
     };
 
-    for file in files {
-        if let Ok(file) = file {
-            if let Some(ext) = file.path().extension() {
-                if ext == "c" {
-                    return true;
+    for file in files.flatten() {
+        if let Some(ext) = file.path().extension() {
+            if ext == "c" {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+        }
+        Err(_) => false,
+    }
+}
+
+// resolves a program the way it will be spawned: absolute paths as-is,
+// paths with a separator relative to `cwd` and bare names through $PATH
+pub fn resolve_executable(program: &str, cwd: &Path) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.is_absolute() {
+        return path.exists().then(|| path.to_path_buf());
+    }
+
+    if program.contains('/') {
+        let path = cwd.join(path);
+        return path.exists().then_some(path);
+    }
+
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+pub fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() {
+        fs::copy(from, to)?;
+        return Ok(());
+    }
+
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn unique_temp_dir(prefix: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!(
+        "{}-{}-{}",
+        prefix,
+        std::process::id(),
+        id
+    ))
+}
+
+pub struct DirSnapshot {
+    root: PathBuf,
+    backup: PathBuf,
+    // relative path and whether it existed when the snapshot was taken
+    entries: Vec<(PathBuf, bool)>,
+}
+
+impl DirSnapshot {
+    pub fn take(root: &Path, paths: &[PathBuf]) -> std::io::Result<Self> {
+        let backup = unique_temp_dir("tests_lib-snapshot");
+        fs::create_dir_all(&backup)?;
+
+        let mut entries = Vec::new();
+        for path in paths {
+            let source = root.join(path);
+            let existed = source.exists();
+            if existed {
+                let target = backup.join(path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
                 }
+                copy_recursively(&source, &target)?;
+            }
+            entries.push((path.clone(), existed));
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            backup,
+            entries,
+        })
+    }
+
+    pub fn restore(self) -> std::io::Result<()> {
+        for (path, existed) in &self.entries {
+            let target = self.root.join(path);
+            remove_path(&target)?;
+            if *existed {
+                copy_recursively(&self.backup.join(path), &target)?;
+            }
+        }
+        fs::remove_dir_all(&self.backup)
+    }
+}
+
+// drops ANSI escape sequences (colors, cursor movement) from terminal output
+pub fn strip_ansi(bytes: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter().peekable();
+
+    while let Some(&byte) = iter.next() {
+        if byte != 0x1b {
+            stripped.push(byte);
+            continue;
+        }
+
+        // CSI sequences run until a final byte in 0x40..=0x7e
+        if iter.peek() == Some(&&b'[') {
+            iter.next();
+            for &byte in iter.by_ref() {
+                if (0x40..=0x7e).contains(&byte) {
+                    break;
+                }
+            }
+        } else {
+            iter.next();
+        }
+    }
+
+    stripped
+}
+
+// the first and last lines of `bytes`, `max_lines` in total, around a
+// marker counting the elided ones
+pub fn excerpt_lines(bytes: &[u8], max_lines: usize) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_lines {
+        return text.into_owned();
+    }
+
+    let head = max_lines.div_ceil(2);
+    let tail = max_lines - head;
+    let mut excerpt = String::new();
+    for line in &lines[..head] {
+        excerpt.push_str(line);
+        excerpt.push('\n');
+    }
+    excerpt.push_str(&format!(
+        "... {} lines elided ...\n",
+        lines.len() - max_lines
+    ));
+    for line in &lines[lines.len() - tail..] {
+        excerpt.push_str(line);
+        excerpt.push('\n');
+    }
+    excerpt
+}
+
+// expands `$NAME` and `${NAME}` through `lookup`; unknown variables are
+// kept as written, or returned as the error when `strict`
+pub fn expand_env_vars<F>(
+    text: &str,
+    lookup: F,
+    strict: bool,
+) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, written) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &rest[pos..pos + end + 3]),
+                None => ("", "$"),
+            },
+            None => {
+                let end = after.find(|c| !is_name(c)).unwrap_or(after.len());
+                (&after[..end], &rest[pos..pos + end + 1])
             }
+        };
+
+        // names can't start with a digit, e.g. "$5" is kept
+        let valid = name
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(is_name);
+        match (valid, lookup(name)) {
+            (true, Some(value)) => expanded.push_str(&value),
+            (true, None) if strict => return Err(name.to_string()),
+            _ => expanded.push_str(written),
         }
+        rest = &rest[pos + written.len()..];
     }
 
-    return false;
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// fills `{name}` placeholders through `lookup` in a single pass, so a value
+// holding another `{key}` is kept as written; names in `literal` and a
+// `${NAME}` left by expand_env_vars are kept too, any other unresolved
+// name is returned as the error
+pub fn expand_placeholders<F>(
+    text: &str,
+    lookup: F,
+    literal: &[String],
+) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('{') {
+        let after = &rest[pos + 1..];
+        let name = match after.find('}') {
+            Some(end) => &after[..end],
+            None => "",
+        };
+        let valid = name
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(is_name);
+        if !valid {
+            expanded.push_str(&rest[..=pos]);
+            rest = after;
+            continue;
+        }
+
+        let written = &rest[pos..pos + name.len() + 2];
+        let kept =
+            rest[..pos].ends_with('$') || literal.iter().any(|l| l == name);
+        expanded.push_str(&rest[..pos]);
+        match lookup(name) {
+            _ if kept => expanded.push_str(written),
+            Some(value) => expanded.push_str(&value),
+            None => return Err(name.to_string()),
+        }
+        rest = &rest[pos + written.len()..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// removes every occurrence of an interactive prompt such as "> "
+pub fn strip_prompt(bytes: &[u8], prompt: &str) -> Vec<u8> {
+    let prompt = prompt.as_bytes();
+    if prompt.is_empty() {
+        return bytes.to_vec();
+    }
+
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        if rest.starts_with(prompt) {
+            rest = &rest[prompt.len()..];
+        } else {
+            stripped.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+
+    stripped
+}
+
+// number of descriptors the process currently holds open, None once it has
+// exited or when /proc is unavailable (Linux only)
+pub fn open_fd_count(pid: i32) -> Option<usize> {
+    let entries = fs::read_dir(format!("/proc/{}/fd", pid)).ok()?;
+    Some(entries.flatten().count())
+}
+
+// inodes of the sockets the process currently holds open, None once it has
+// exited (zombies included) or when /proc is unavailable (Linux only)
+pub fn socket_inodes(pid: i32) -> Option<HashSet<u64>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the state follows the parenthesized command, which may hold spaces
+    let state = stat.rsplit_once(')')?.1.split_whitespace().next()?;
+    if state == "Z" || state == "X" {
+        return None;
+    }
+
+    let entries = fs::read_dir(format!("/proc/{}/fd", pid)).ok()?;
+    let inodes = entries
+        .flatten()
+        .filter_map(|entry| fs::read_link(entry.path()).ok())
+        .filter_map(|link| {
+            let link = link.to_str()?;
+            link.strip_prefix("socket:[")?
+                .strip_suffix(']')?
+                .parse()
+                .ok()
+        })
+        .collect();
+    Some(inodes)
+}
+
+// inodes of every TCP socket on the host that some process still holds,
+// from /proc/net/tcp and /proc/net/tcp6; TIME_WAIT sockets have none
+pub fn open_tcp_inodes() -> HashSet<u64> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| fs::read_to_string(table).ok())
+        .flat_map(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_whitespace().nth(9)?.parse().ok())
+                .filter(|&inode| inode != 0)
+                .collect::<Vec<u64>>()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildProcess {
+    pub pid: i32,
+    pub command: String,
+    // exited but never waited for
+    pub zombie: bool,
+}
+
+// the direct children of `parent` that still have a /proc entry, running
+// or zombie; empty when /proc is unavailable (Linux only)
+pub fn child_processes(parent: i32) -> Vec<ChildProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut children: Vec<ChildProcess> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .filter_map(|pid| {
+            let stat =
+                fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            let (head, rest) = stat.rsplit_once(')')?;
+            let command = head.split_once('(')?.1.to_string();
+            let mut fields = rest.split_whitespace();
+            let state = fields.next()?;
+            let ppid: i32 = fields.next()?.parse().ok()?;
+            (ppid == parent).then(|| ChildProcess {
+                pid,
+                command,
+                zombie: state == "Z",
+            })
+        })
+        .collect();
+    children.sort_by_key(|child| child.pid);
+    children
+}
+
+// the process's peak resident set size (VmHWM), None once it has exited or
+// when /proc is unavailable (Linux only)
+pub fn peak_rss_bytes(pid: i32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn host_elf_machine() -> Option<u16> {
+    match std::env::consts::ARCH {
+        "x86" => Some(3),
+        "arm" => Some(40),
+        "x86_64" => Some(62),
+        "aarch64" => Some(183),
+        "riscv64" => Some(243),
+        _ => None,
+    }
+}
+
+// checks the ELF magic and that the class, byte order and machine match the
+// host, so e.g. a committed Mach-O binary is rejected up front
+pub fn is_valid_elf(path: &Path) -> bool {
+    let mut header = [0u8; 20];
+    let read = fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header));
+    if read.is_err() || header[..4] != *b"\x7fELF" {
+        return false;
+    }
+
+    let class = if cfg!(target_pointer_width = "64") {
+        2
+    } else {
+        1
+    };
+    let data = if cfg!(target_endian = "little") { 1 } else { 2 };
+    let machine = match header[5] {
+        1 => u16::from_le_bytes([header[18], header[19]]),
+        _ => u16::from_be_bytes([header[18], header[19]]),
+    };
+
+    // unknown hosts only get the magic, class and byte order checked
+    header[4] == class
+        && header[5] == data
+        && host_elf_machine().is_none_or(|host| host == machine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_drops_escape_sequences() {
+        let colored = b"\x1b[1;31merr\x1b[0mor: \x1b[Kbad\x1b7";
+        assert_eq!(strip_ansi(colored), b"error: bad");
+        assert_eq!(strip_ansi(b"plain"), b"plain");
+    }
+
+    #[test]
+    fn is_valid_elf_checks_the_header() {
+        assert!(is_valid_elf(Path::new("/bin/sh")));
+
+        let dir = unique_temp_dir("tests-lib-test");
+        fs::create_dir_all(&dir).unwrap();
+        let macho = dir.join("macho");
+        fs::write(&macho, b"\xcf\xfa\xed\xfe\x07\x00\x00\x01").unwrap();
+        assert!(!is_valid_elf(&macho));
+
+        // the host's header with the machine field changed
+        let mut header = fs::read("/bin/sh").unwrap()[..20].to_vec();
+        header[18] ^= 0xff;
+        let foreign = dir.join("foreign");
+        fs::write(&foreign, header).unwrap();
+        assert!(!is_valid_elf(&foreign));
+        assert!(!is_valid_elf(&dir.join("missing")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn strip_prompt_removes_every_prompt() {
+        let output = b"> answer\n> answer2\n> ";
+        assert_eq!(strip_prompt(output, "> "), b"answer\nanswer2\n");
+        assert_eq!(strip_prompt(b">> a", ">"), b" a");
+        assert_eq!(strip_prompt(output, ""), output);
+    }
+
+    #[test]
+    fn excerpt_lines_keeps_the_head_and_tail() {
+        let output = b"1\n2\n3\n4\n5\n6\n";
+        assert_eq!(
+            excerpt_lines(output, 3),
+            "1\n2\n... 3 lines elided ...\n6\n"
+        );
+        assert_eq!(excerpt_lines(output, 6), "1\n2\n3\n4\n5\n6\n");
+        assert_eq!(excerpt_lines(output, 0), "... 6 lines elided ...\n");
+    }
+
+    #[test]
+    fn expand_env_vars_reads_plain_and_braced_names() {
+        let lookup = |name: &str| (name == "DATA").then(|| String::from("/d"));
+        let expand = |text, strict| expand_env_vars(text, lookup, strict);
+        assert_eq!(expand("$DATA/in ${DATA}x", false).unwrap(), "/d/in /dx");
+        assert_eq!(expand("$5 ${ $ $UNSET", false).unwrap(), "$5 ${ $ $UNSET");
+        assert_eq!(expand("$UNSET", true).unwrap_err(), "UNSET");
+        assert_eq!(expand("cost: $5", true).unwrap(), "cost: $5");
+    }
+
+    #[test]
+    fn expand_placeholders_fills_names_in_one_pass() {
+        let lookup = |name: &str| match name {
+            "a" => Some(String::from("{b}")),
+            "b" => Some(String::from("2")),
+            _ => None,
+        };
+        let literal = [String::from("print")];
+        let expand = |text| expand_placeholders(text, lookup, &literal);
+        assert_eq!(expand("{a} {b}").unwrap(), "{b} 2");
+        let kept = "{print} ${b} {} {1x} {";
+        assert_eq!(expand(kept).unwrap(), kept);
+        assert_eq!(expand("{admin_prot}").unwrap_err(), "admin_prot");
+    }
 }